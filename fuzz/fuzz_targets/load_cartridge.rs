@@ -0,0 +1,12 @@
+// Any file should load or give an error, never panic
+//
+// cargo +nightly fuzz run load_cartridge
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lynes::cartridge::Cartridge;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Cartridge::load_bytes(data);
+});
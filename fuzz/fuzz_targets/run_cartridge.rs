@@ -0,0 +1,22 @@
+// Loads the input as a ROM image and runs whatever loads for a few frames
+//
+// cargo +nightly fuzz run run_cartridge
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lynes::{cartridge::Cartridge, NES};
+
+const FRAMES: usize = 3;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(cart) = Cartridge::load_bytes(data) else {
+        return;
+    };
+
+    let mut nes = NES::from_cartridge(cart);
+
+    for _ in 0..FRAMES {
+        nes.run_frame();
+    }
+});
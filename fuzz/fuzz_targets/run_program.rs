@@ -0,0 +1,26 @@
+// Runs the input as PRG ROM on a valid NROM cart, which gets much further into
+// the CPU and PPU than random headers do
+//
+// cargo +nightly fuzz run run_program
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lynes::{cartridge::test::RomBuilder, NES};
+
+const FRAMES: usize = 3;
+const PRG_ROM_SIZE: usize = 0x8000;
+
+fuzz_target!(|data: &[u8]| {
+    let mut prg_rom = data.to_vec();
+    prg_rom.truncate(PRG_ROM_SIZE);
+
+    // Always starts at $8000, NMI and IRQ go wherever the input points them
+    let builder = RomBuilder::new().prg_rom(prg_rom).reset_vector(0x8000);
+
+    let mut nes = NES::from_cartridge(builder.cartridge());
+
+    for _ in 0..FRAMES {
+        nes.run_frame();
+    }
+});
@@ -1,7 +1,59 @@
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+use crate::region::Region;
+
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
 const PRG_ROM_PAGE_SIZE: usize = 16384;
 const CHR_ROM_PAGE_SIZE: usize = 8192;
 
+#[derive(Debug, PartialEq)]
+pub enum CartridgeError {
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
+    // Too short for a header or missing the NES<EOF> tag
+    InvalidHeader,
+    // Archaic iNES and other headers with bits 2-3 of byte 7 set to 1 or 3
+    UnsupportedVersion,
+    // NES 2.0 exponent-multiplier ROM sizes
+    UnsupportedRomSize,
+    // NES 2.0 CHR RAM under 8KB, which would need a mapper to mirror it
+    UnsupportedChrRamSize(usize),
+    // The header asks for more PRG and CHR ROM than the file has
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            CartridgeError::Io(kind) => write!(f, "unable to read the file: {}", kind),
+            CartridgeError::InvalidHeader => write!(f, "not an iNES or NES 2.0 file"),
+            CartridgeError::UnsupportedVersion => write!(f, "unsupported iNES version"),
+            CartridgeError::UnsupportedRomSize => {
+                write!(f, "exponent-multiplier ROM sizes are not supported")
+            }
+            CartridgeError::UnsupportedChrRamSize(size) => {
+                write!(
+                    f,
+                    "{} bytes of CHR RAM is less than the 8KB supported",
+                    size
+                )
+            }
+            CartridgeError::Truncated { expected, actual } => write!(
+                f,
+                "file is {} bytes but the header describes {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum ScreenMirroring {
     Vertical,
@@ -12,28 +64,35 @@ pub enum ScreenMirroring {
 pub struct Cartridge {
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
+    // chr_rom is writable CHR RAM instead
+    pub chr_ram: bool,
     pub mapper: u8, // TODO: implement mappers
     pub screen_mirroring: ScreenMirroring,
+    pub region: Region,
 }
 
 impl Cartridge {
-    pub fn load(file: &str) -> Self {
-        let bytes = std::fs::read(file).expect("Unable to read Cartridge file");
+    #[cfg(feature = "std")]
+    pub fn load(file: &str) -> Result<Self, CartridgeError> {
+        let bytes = std::fs::read(file).map_err(|err| CartridgeError::Io(err.kind()))?;
+
         Self::load_bytes(&bytes)
     }
 
-    fn load_bytes(bytes: &Vec<u8>) -> Self {
-        if bytes[0..4] != NES_TAG {
-            panic!("Invalid NES file");
+    pub fn load_bytes(bytes: &[u8]) -> Result<Self, CartridgeError> {
+        if bytes.len() < HEADER_SIZE || bytes[0..4] != NES_TAG {
+            return Err(CartridgeError::InvalidHeader);
         }
 
         let mapper = (bytes[7] & 0xF0) | (bytes[6] >> 4);
         let ines_ver = (bytes[7] >> 2) & 0x03;
 
-        if ines_ver != 0 {
-            panic!("Unsupported iNES version");
+        if ines_ver != 0 && ines_ver != 2 {
+            return Err(CartridgeError::UnsupportedVersion);
         }
 
+        let nes2 = ines_ver == 2;
+
         let four_screen = bytes[6] & 0x08 != 0;
         let vertical_mirroring = bytes[6] & 0x01 != 0;
 
@@ -45,20 +104,77 @@ impl Cartridge {
             ScreenMirroring::Horizontal
         };
 
-        let prg_rom_size = bytes[4] as usize * PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = bytes[5] as usize * CHR_ROM_PAGE_SIZE;
+        // NES 2.0 keeps the upper bits of the ROM sizes in byte 9 and the timing
+        // in byte 12, iNES only has a rarely set PAL bit in byte 9
+        let (prg_rom_pages, chr_rom_pages, region) = if nes2 {
+            if bytes[9] & 0x0F == 0x0F || bytes[9] & 0xF0 == 0xF0 {
+                return Err(CartridgeError::UnsupportedRomSize);
+            }
+
+            let region = match bytes[12] & 0x03 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            };
+
+            (
+                (bytes[9] as usize & 0x0F) << 8 | bytes[4] as usize,
+                (bytes[9] as usize >> 4) << 8 | bytes[5] as usize,
+                region,
+            )
+        } else {
+            let region = if bytes[9] & 0x01 != 0 {
+                Region::Pal
+            } else {
+                Region::Ntsc
+            };
+
+            (bytes[4] as usize, bytes[5] as usize, region)
+        };
+
+        let prg_rom_size = prg_rom_pages * PRG_ROM_PAGE_SIZE;
+        let chr_rom_size = chr_rom_pages * CHR_ROM_PAGE_SIZE;
 
         let skip_trainer = bytes[6] & 0x04 != 0;
 
-        let prg_rom_start = 16 + if skip_trainer { 512 } else { 0 };
+        let prg_rom_start = HEADER_SIZE + if skip_trainer { TRAINER_SIZE } else { 0 };
         let chr_rom_start = prg_rom_start + prg_rom_size;
+        let chr_rom_end = chr_rom_start + chr_rom_size;
 
-        Self {
-            prg_rom: bytes[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
-            chr_rom: bytes[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
-            mapper: mapper,
-            screen_mirroring: screen_mirroring,
+        if bytes.len() < chr_rom_end {
+            return Err(CartridgeError::Truncated {
+                expected: chr_rom_end,
+                actual: bytes.len(),
+            });
         }
+
+        // Boards without CHR ROM have CHR RAM instead. NES 2.0 stores its size
+        // in byte 11 as 64 << shift, iNES and a shift of 0 mean 8KB.
+        let chr_rom = if chr_rom_size == 0 {
+            let shift = bytes[11] & 0x0F;
+            let chr_ram_size = if nes2 && shift != 0 {
+                64 << shift
+            } else {
+                CHR_ROM_PAGE_SIZE
+            };
+
+            if chr_ram_size < CHR_ROM_PAGE_SIZE {
+                return Err(CartridgeError::UnsupportedChrRamSize(chr_ram_size));
+            }
+
+            vec![0; chr_ram_size]
+        } else {
+            bytes[chr_rom_start..chr_rom_end].to_vec()
+        };
+
+        Ok(Self {
+            prg_rom: bytes[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom,
+            chr_ram: chr_rom_size == 0,
+            mapper,
+            screen_mirroring,
+            region,
+        })
     }
 }
 
@@ -92,27 +208,274 @@ pub mod test {
         result
     }
 
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum HeaderFormat {
+        Ines,
+        Nes2,
+    }
+
+    // Builds iNES and NES 2.0 images for tests, e.g.
+    //
+    //   let rom = RomBuilder::new()
+    //       .mapper(1)
+    //       .prg_rom(assemble(source).unwrap())
+    //       .chr_ram(0x2000)
+    //       .build();
+    //
+    // PRG ROM is padded with zeros to a whole number of 16KB pages and the
+    // vectors are written to its last 6 bytes, where the CPU sees them with
+    // NROM. Vectors that aren't set are left as they are in the PRG ROM.
+    #[derive(Clone)]
+    pub struct RomBuilder {
+        format: HeaderFormat,
+        mapper: u8,
+        mirroring: ScreenMirroring,
+        battery: bool,
+        trainer: Option<Vec<u8>>,
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        prg_ram_size: usize,
+        chr_ram_size: usize,
+        region: Region,
+        nmi_vector: Option<u16>,
+        reset_vector: Option<u16>,
+        irq_vector: Option<u16>,
+    }
+
+    impl Default for RomBuilder {
+        fn default() -> Self {
+            Self {
+                format: HeaderFormat::Ines,
+                mapper: 0,
+                mirroring: ScreenMirroring::Horizontal,
+                battery: false,
+                trainer: None,
+                prg_rom: vec![0; 2 * PRG_ROM_PAGE_SIZE],
+                chr_rom: vec![0; CHR_ROM_PAGE_SIZE],
+                prg_ram_size: 0,
+                chr_ram_size: 0,
+                region: Region::Ntsc,
+                nmi_vector: None,
+                reset_vector: None,
+                irq_vector: None,
+            }
+        }
+    }
+
+    impl RomBuilder {
+        // 32KB of PRG ROM and 8KB of CHR ROM, all zeros, on NROM
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn format(mut self, format: HeaderFormat) -> Self {
+            self.format = format;
+            self
+        }
+
+        pub fn mapper(mut self, mapper: u8) -> Self {
+            self.mapper = mapper;
+            self
+        }
+
+        pub fn mirroring(mut self, mirroring: ScreenMirroring) -> Self {
+            self.mirroring = mirroring;
+            self
+        }
+
+        pub fn battery(mut self, battery: bool) -> Self {
+            self.battery = battery;
+            self
+        }
+
+        // Padded or cut to 512 bytes
+        pub fn trainer(mut self, trainer: Vec<u8>) -> Self {
+            self.trainer = Some(trainer);
+            self
+        }
+
+        pub fn prg_rom(mut self, prg_rom: Vec<u8>) -> Self {
+            self.prg_rom = prg_rom;
+            self
+        }
+
+        // Rounded up to whole 8KB pages, empty for boards with CHR RAM
+        pub fn chr_rom(mut self, chr_rom: Vec<u8>) -> Self {
+            self.chr_rom = chr_rom;
+            self
+        }
+
+        // Only NES 2.0 headers store the RAM sizes, which have to be a power of
+        // two of at least 128 bytes. iNES stores PRG RAM in 8KB units and
+        // implies 8KB of CHR RAM when there's no CHR ROM.
+        pub fn prg_ram(mut self, size: usize) -> Self {
+            self.prg_ram_size = size;
+            self
+        }
+
+        // Also removes the CHR ROM
+        pub fn chr_ram(mut self, size: usize) -> Self {
+            self.chr_ram_size = size;
+            self.chr_rom = vec![];
+            self
+        }
+
+        pub fn region(mut self, region: Region) -> Self {
+            self.region = region;
+            self
+        }
+
+        pub fn nmi_vector(mut self, addr: u16) -> Self {
+            self.nmi_vector = Some(addr);
+            self
+        }
+
+        pub fn reset_vector(mut self, addr: u16) -> Self {
+            self.reset_vector = Some(addr);
+            self
+        }
+
+        pub fn irq_vector(mut self, addr: u16) -> Self {
+            self.irq_vector = Some(addr);
+            self
+        }
+
+        pub fn build(&self) -> Vec<u8> {
+            let prg_rom = self.padded_prg_rom();
+            let mut chr_rom = self.chr_rom.clone();
+            chr_rom.resize(
+                chr_rom.len().div_ceil(CHR_ROM_PAGE_SIZE) * CHR_ROM_PAGE_SIZE,
+                0,
+            );
+
+            let trainer = self.trainer.clone().map(|mut trainer| {
+                trainer.resize(TRAINER_SIZE, 0);
+                trainer
+            });
+
+            let header = self.header(
+                prg_rom.len() / PRG_ROM_PAGE_SIZE,
+                chr_rom.len() / CHR_ROM_PAGE_SIZE,
+            );
+
+            create_rom(TestRom {
+                header,
+                trainer,
+                prg_rom,
+                chr_rom,
+            })
+        }
+
+        pub fn cartridge(&self) -> Cartridge {
+            Cartridge::load_bytes(&self.build()).unwrap()
+        }
+
+        fn padded_prg_rom(&self) -> Vec<u8> {
+            let mut prg_rom = self.prg_rom.clone();
+            let pages = prg_rom.len().div_ceil(PRG_ROM_PAGE_SIZE).max(1);
+            prg_rom.resize(pages * PRG_ROM_PAGE_SIZE, 0);
+
+            let vectors = prg_rom.len() - 6;
+
+            for (i, vector) in [self.nmi_vector, self.reset_vector, self.irq_vector]
+                .into_iter()
+                .enumerate()
+            {
+                if let Some(addr) = vector {
+                    let start = vectors + i * 2;
+                    prg_rom[start..start + 2].copy_from_slice(&addr.to_le_bytes());
+                }
+            }
+
+            prg_rom
+        }
+
+        fn header(&self, prg_rom_pages: usize, chr_rom_pages: usize) -> Vec<u8> {
+            let max_pages = match self.format {
+                HeaderFormat::Ines => 0xFF,
+                HeaderFormat::Nes2 => 0xEFF,
+            };
+
+            assert!(
+                prg_rom_pages <= max_pages && chr_rom_pages <= max_pages,
+                "{} PRG and {} CHR pages don't fit in the header, the limit is {}",
+                prg_rom_pages,
+                chr_rom_pages,
+                max_pages
+            );
+
+            let mut header = vec![0; 16];
+            header[0..4].copy_from_slice(&NES_TAG);
+            header[4] = prg_rom_pages as u8;
+            header[5] = chr_rom_pages as u8;
+
+            header[6] = (self.mapper << 4)
+                | match self.mirroring {
+                    ScreenMirroring::Horizontal => 0,
+                    ScreenMirroring::Vertical => 0x01,
+                    ScreenMirroring::FourScreen => 0x08,
+                }
+                | if self.battery { 0x02 } else { 0 }
+                | if self.trainer.is_some() { 0x04 } else { 0 };
+            header[7] = self.mapper & 0xF0;
+
+            match self.format {
+                HeaderFormat::Ines => {
+                    header[8] = self.prg_ram_size.div_ceil(0x2000) as u8;
+                    header[9] = (self.region == Region::Pal) as u8;
+                }
+                HeaderFormat::Nes2 => {
+                    header[7] |= 0x08;
+                    header[9] = ((chr_rom_pages >> 8) << 4 | prg_rom_pages >> 8) as u8;
+
+                    // Battery backed PRG RAM goes in the upper nibble
+                    let prg_ram = ram_shift(self.prg_ram_size);
+                    header[10] = if self.battery { prg_ram << 4 } else { prg_ram };
+                    header[11] = ram_shift(self.chr_ram_size);
+                    header[12] = match self.region {
+                        Region::Ntsc => 0,
+                        Region::Pal => 1,
+                        Region::Dendy => 3,
+                    };
+                }
+            }
+
+            header
+        }
+    }
+
+    // NES 2.0 stores RAM sizes as 64 << shift, with 0 for none
+    fn ram_shift(size: usize) -> u8 {
+        if size == 0 {
+            return 0;
+        }
+
+        assert!(
+            size.is_power_of_two() && size >= 128,
+            "{} bytes of RAM can't be stored in a NES 2.0 header",
+            size
+        );
+
+        (size.trailing_zeros() - 6) as u8
+    }
+
+    // A 32KB NROM-sized image with the program at $8000, which it always resets
+    // to. Use RomBuilder directly for programs that set their own vectors.
     #[allow(dead_code)]
     pub fn test_rom(prg_rom: Option<Vec<u8>>) -> Cartridge {
-        let prg_rom = match prg_rom {
-            Some(p) => {
-                let mut result = vec![0; 2 * PRG_ROM_PAGE_SIZE];
-                result[..p.len()].copy_from_slice(&p);
-                result[0x7FFD] = 0x80;
-                result
-            }
-            None => vec![1; 2 * PRG_ROM_PAGE_SIZE],
-        };
-        let test_rom = create_rom(TestRom {
-            header: vec![
-                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
-            ],
-            trainer: None,
-            prg_rom: prg_rom,
-            chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
-        });
+        let builder = RomBuilder::new()
+            .mapper(3)
+            .mirroring(ScreenMirroring::Vertical)
+            .chr_rom(vec![2; CHR_ROM_PAGE_SIZE]);
 
-        Cartridge::load_bytes(&test_rom)
+        match prg_rom {
+            Some(mut p) => {
+                p.resize(2 * PRG_ROM_PAGE_SIZE, 0);
+
+                builder.prg_rom(p).reset_vector(0x8000).cartridge()
+            }
+            None => builder.prg_rom(vec![1; 2 * PRG_ROM_PAGE_SIZE]).cartridge(),
+        }
     }
 
     #[test]
@@ -126,7 +489,7 @@ pub mod test {
             chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
         });
 
-        let rom: Cartridge = Cartridge::load_bytes(&test_rom);
+        let rom = Cartridge::load_bytes(&test_rom).unwrap();
 
         assert_eq!(rom.chr_rom, vec!(2; 1 * CHR_ROM_PAGE_SIZE));
         assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
@@ -160,7 +523,7 @@ pub mod test {
             chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
         });
 
-        let rom: Cartridge = Cartridge::load_bytes(&test_rom);
+        let rom = Cartridge::load_bytes(&test_rom).unwrap();
 
         assert_eq!(rom.chr_rom, vec!(2; 1 * CHR_ROM_PAGE_SIZE));
         assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
@@ -169,16 +532,210 @@ pub mod test {
     }
 
     #[test]
-    #[should_panic]
-    fn test_nes2_is_not_supported() {
+    fn test_nes2_timing() {
+        for (timing, region) in [
+            (0, Region::Ntsc),
+            (1, Region::Pal),
+            (2, Region::Ntsc),
+            (3, Region::Dendy),
+        ] {
+            let test_rom = create_rom(TestRom {
+                header: vec![
+                    0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x31, 0x8, 00, 00, 00, 00, timing, 00, 00,
+                    00,
+                ],
+                trainer: None,
+                prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+                chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+            });
+
+            let rom = Cartridge::load_bytes(&test_rom).unwrap();
+
+            assert_eq!(rom.prg_rom, vec!(1; PRG_ROM_PAGE_SIZE));
+            assert_eq!(rom.chr_rom, vec!(2; CHR_ROM_PAGE_SIZE));
+            assert_eq!(rom.region, region);
+        }
+    }
+
+    #[test]
+    fn test_ines_pal_flag() {
         let test_rom = create_rom(TestRom {
             header: vec![
-                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x31, 0x8, 00, 00, 00, 00, 00, 00, 00, 00,
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x31, 00, 00, 0x01, 00, 00, 00, 00, 00, 00,
             ],
             trainer: None,
-            prg_rom: vec![1; 1 * PRG_ROM_PAGE_SIZE],
-            chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
         });
-        let _ = Cartridge::load_bytes(&test_rom);
+
+        assert_eq!(
+            Cartridge::load_bytes(&test_rom).unwrap().region,
+            Region::Pal
+        );
+    }
+
+    #[test]
+    fn test_archaic_ines_is_not_supported() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x31, 0x4, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+        assert_eq!(
+            Cartridge::load_bytes(&test_rom).err(),
+            Some(CartridgeError::UnsupportedVersion)
+        );
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert_eq!(
+            Cartridge::load_bytes(&[]).err(),
+            Some(CartridgeError::InvalidHeader)
+        );
+        assert_eq!(
+            Cartridge::load_bytes(&[0x4E, 0x45, 0x53, 0x1A, 1, 1]).err(),
+            Some(CartridgeError::InvalidHeader)
+        );
+        assert_eq!(
+            Cartridge::load_bytes(&[0; 64]).err(),
+            Some(CartridgeError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn test_truncated_rom() {
+        let rom = RomBuilder::new().trainer(vec![]).build();
+
+        for len in HEADER_SIZE..rom.len() {
+            assert_eq!(
+                Cartridge::load_bytes(&rom[..len]).err(),
+                Some(CartridgeError::Truncated {
+                    expected: rom.len(),
+                    actual: len
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_chr_ram_is_allocated() {
+        let cart = RomBuilder::new().chr_ram(0x2000).cartridge();
+
+        assert_eq!(cart.chr_rom, vec![0; CHR_ROM_PAGE_SIZE]);
+        assert!(cart.chr_ram);
+    }
+
+    #[test]
+    fn nes2_chr_ram_size_comes_from_the_header() {
+        let cart = RomBuilder::new()
+            .format(HeaderFormat::Nes2)
+            .chr_ram(0x8000)
+            .cartridge();
+
+        assert_eq!(cart.chr_rom.len(), 0x8000);
+        assert!(cart.chr_ram);
+    }
+
+    #[test]
+    fn nes2_chr_ram_under_8kb_is_rejected() {
+        let rom = RomBuilder::new()
+            .format(HeaderFormat::Nes2)
+            .chr_ram(0x800)
+            .build();
+
+        assert_eq!(
+            Cartridge::load_bytes(&rom).err(),
+            Some(CartridgeError::UnsupportedChrRamSize(0x800))
+        );
+    }
+
+    #[test]
+    fn builder_writes_an_ines_header() {
+        let rom = RomBuilder::new()
+            .mapper(0x42)
+            .mirroring(ScreenMirroring::Vertical)
+            .battery(true)
+            .prg_rom(vec![1; 3 * PRG_ROM_PAGE_SIZE])
+            .prg_ram(0x2000)
+            .region(Region::Pal)
+            .build();
+
+        assert_eq!(
+            rom[..16],
+            [0x4E, 0x45, 0x53, 0x1A, 3, 1, 0x23, 0x40, 1, 1, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(rom.len(), 16 + 3 * PRG_ROM_PAGE_SIZE + CHR_ROM_PAGE_SIZE);
+    }
+
+    #[test]
+    fn builder_writes_a_nes2_header() {
+        let rom = RomBuilder::new()
+            .format(HeaderFormat::Nes2)
+            .mirroring(ScreenMirroring::FourScreen)
+            .battery(true)
+            .prg_ram(0x2000)
+            .chr_ram(0x2000)
+            .region(Region::Dendy)
+            .build();
+
+        assert_eq!(
+            rom[..16],
+            [0x4E, 0x45, 0x53, 0x1A, 2, 0, 0x0A, 0x08, 0, 0, 0x70, 0x07, 3, 0, 0, 0]
+        );
+        assert_eq!(rom.len(), 16 + 2 * PRG_ROM_PAGE_SIZE);
+    }
+
+    #[test]
+    #[should_panic(expected = "don't fit in the header")]
+    fn builder_refuses_oversized_ines_roms() {
+        RomBuilder::new()
+            .prg_rom(vec![0; 256 * PRG_ROM_PAGE_SIZE])
+            .build();
+    }
+
+    #[test]
+    fn builder_round_trips_through_the_loader() {
+        for format in [HeaderFormat::Ines, HeaderFormat::Nes2] {
+            let cart = RomBuilder::new()
+                .format(format)
+                .mapper(1)
+                .mirroring(ScreenMirroring::Vertical)
+                .trainer(vec![0xFF; 4])
+                .prg_rom(vec![1; 100])
+                .chr_rom(vec![2; 10])
+                .region(Region::Pal)
+                .cartridge();
+
+            let mut prg_rom = vec![1; 100];
+            prg_rom.resize(PRG_ROM_PAGE_SIZE, 0);
+            let mut chr_rom = vec![2; 10];
+            chr_rom.resize(CHR_ROM_PAGE_SIZE, 0);
+
+            assert_eq!(cart.prg_rom, prg_rom);
+            assert_eq!(cart.chr_rom, chr_rom);
+            assert_eq!(cart.mapper, 1);
+            assert_eq!(cart.screen_mirroring, ScreenMirroring::Vertical);
+            assert_eq!(cart.region, Region::Pal);
+        }
+    }
+
+    #[test]
+    fn builder_writes_vectors_at_the_end_of_prg_rom() {
+        let cart = RomBuilder::new()
+            .prg_rom(vec![0xEA; PRG_ROM_PAGE_SIZE])
+            .nmi_vector(0xC123)
+            .reset_vector(0xC000)
+            .irq_vector(0xC456)
+            .cartridge();
+
+        assert_eq!(
+            cart.prg_rom[PRG_ROM_PAGE_SIZE - 6..],
+            [0x23, 0xC1, 0x00, 0xC0, 0x56, 0xC4]
+        );
+        assert_eq!(cart.prg_rom[PRG_ROM_PAGE_SIZE - 7], 0xEA);
     }
 }
@@ -0,0 +1,733 @@
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+use super::{instructions::OPCODES, AddrMode};
+
+// A small 6502 assembler for writing tests. It produces the 32KB PRG image
+// mapped at $8000-$FFFF that cartridge::test::test_rom takes.
+//
+//   ; comments run to the end of the line
+//   PPUCTRL = $2000        constants, defined before use to get zero page
+//   .org $8000             where the following code goes, $8000 by default
+//   .reset start           the reset, NMI and IRQ/BRK vectors, reset is $8000
+//   .nmi nmi               by default and the others are left at 0
+//   .irq irq
+//   start: LDA #$80        labels end with a colon
+//          STA PPUCTRL
+//          LDA table+1,X   operands can add or subtract, and <label or >label
+//          JMP (pointer)   take the low or high byte
+//   table: .byte 1, $02, %11, <start
+//   pointer: .word start
+//
+// Numbers are decimal, $hex or %binary. Illegal opcodes use the names from the
+// instruction table, a legal opcode is picked when both have the same mode.
+
+pub const PRG_START: u16 = 0x8000;
+
+const PRG_SIZE: usize = 0x8000;
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+#[derive(Debug, PartialEq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(PartialEq)]
+enum BytePart {
+    Whole,
+    Low,
+    High,
+}
+
+enum Term {
+    Number(u16),
+    Label(String),
+}
+
+struct Expression {
+    part: BytePart,
+    // Added together, the bool is set for terms that are subtracted
+    terms: Vec<(bool, Term)>,
+}
+
+enum Data {
+    Instruction {
+        opcode: u8,
+        mode: AddrMode,
+        operand: Option<Expression>,
+    },
+    Bytes(Vec<Expression>),
+    Words(Vec<Expression>),
+}
+
+struct Statement {
+    line: usize,
+    addr: u16,
+    size: usize,
+    data: Data,
+}
+
+#[derive(Default)]
+struct Assembler {
+    symbols: BTreeMap<String, u16>,
+    statements: Vec<Statement>,
+    vectors: Vec<(u16, Expression, usize)>,
+    addr: u32,
+    line: usize,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    let mut assembler = Assembler {
+        addr: PRG_START as u32,
+        ..Default::default()
+    };
+
+    for (index, line) in source.lines().enumerate() {
+        assembler.line = index + 1;
+
+        assembler
+            .parse_line(line)
+            .map_err(|message| AssemblyError {
+                line: assembler.line,
+                message,
+            })?;
+    }
+
+    assembler.emit()
+}
+
+impl Assembler {
+    // First pass, works out where everything goes and defines the labels
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.split(';').next().unwrap().trim();
+
+        if let Some((name, value)) = line.split_once('=') {
+            let name = name.trim();
+            check_identifier(name)?;
+
+            let value = parse_expression(value.trim())?;
+            let value = self
+                .evaluate(&value)
+                .ok_or_else(|| format!("{} must be defined before {}", value.describe(), name))?;
+
+            return self.define(name, value);
+        }
+
+        let line = match line.split_once(':') {
+            Some((label, rest)) if is_identifier(label.trim()) => {
+                self.define(label.trim(), self.addr as u16)?;
+                rest.trim()
+            }
+            _ => line,
+        };
+
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let (keyword, operand) = match line.split_once(char::is_whitespace) {
+            Some((keyword, operand)) => (keyword, operand.trim()),
+            None => (line, ""),
+        };
+
+        if let Some(directive) = keyword.strip_prefix('.') {
+            return self.parse_directive(&directive.to_ascii_lowercase(), operand);
+        }
+
+        self.parse_instruction(&keyword.to_ascii_uppercase(), operand)
+    }
+
+    fn parse_directive(&mut self, directive: &str, operand: &str) -> Result<(), String> {
+        match directive {
+            "org" => {
+                let addr = self
+                    .evaluate(&parse_expression(operand)?)
+                    .ok_or("the address of .org must be defined before it")?;
+
+                if addr < PRG_START {
+                    return Err(format!("${:04X} is outside of PRG ROM", addr));
+                }
+
+                self.addr = addr as u32;
+            }
+            "byte" | "word" => {
+                let values = operand
+                    .split(',')
+                    .map(|value| parse_expression(value.trim()))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let (size, data) = if directive == "byte" {
+                    (values.len(), Data::Bytes(values))
+                } else {
+                    (values.len() * 2, Data::Words(values))
+                };
+
+                self.push(data, size)?;
+            }
+            "nmi" | "reset" | "irq" => {
+                let vector = match directive {
+                    "nmi" => NMI_VECTOR,
+                    "reset" => RESET_VECTOR,
+                    _ => IRQ_VECTOR,
+                };
+
+                self.vectors
+                    .push((vector, parse_expression(operand)?, self.line));
+            }
+            _ => return Err(format!("unknown directive .{}", directive)),
+        }
+
+        Ok(())
+    }
+
+    fn parse_instruction(&mut self, mnemonic: &str, operand_text: &str) -> Result<(), String> {
+        if find_opcode(mnemonic, |_| true).is_none() {
+            return Err(format!("unknown instruction {}", mnemonic));
+        }
+
+        let (modes, operand) = parse_operand(operand_text)?;
+
+        // Operands that are already known to fit use zero page when the
+        // instruction has it, anything else is absolute
+        let zero_page = match &operand {
+            Some(operand) => self.evaluate(operand).is_some_and(|value| value <= 0xFF),
+            None => false,
+        };
+
+        let (opcode, mode) = modes
+            .iter()
+            .filter(|mode| zero_page || !is_zero_page(mode))
+            .find_map(|mode| {
+                find_opcode(mnemonic, |other| other == mode).map(|opcode| (opcode, *mode))
+            })
+            .ok_or_else(|| format!("{} can't take the operand \"{}\"", mnemonic, operand_text))?;
+
+        let size = OPCODES[opcode as usize].len as usize;
+
+        self.push(
+            Data::Instruction {
+                opcode,
+                mode,
+                operand,
+            },
+            size,
+        )
+    }
+
+    fn push(&mut self, data: Data, size: usize) -> Result<(), String> {
+        let end = self.addr + size as u32;
+
+        if end > 0x10000 {
+            return Err("past the end of PRG ROM".to_string());
+        }
+
+        // Only .org can move back over code that's already there
+        let overlap = self.statements.iter().find(|statement| {
+            let start = statement.addr as u32;
+
+            self.addr < start + statement.size as u32 && start < end
+        });
+
+        if let Some(statement) = overlap {
+            return Err(format!(
+                "${:04X} overlaps the bytes from line {}",
+                self.addr, statement.line
+            ));
+        }
+
+        self.statements.push(Statement {
+            line: self.line,
+            addr: self.addr as u16,
+            size,
+            data,
+        });
+        self.addr = end;
+
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, value: u16) -> Result<(), String> {
+        match self.symbols.insert(name.to_string(), value) {
+            Some(_) => Err(format!("{} is already defined", name)),
+            None => Ok(()),
+        }
+    }
+
+    // None when a label isn't defined yet
+    fn evaluate(&self, expression: &Expression) -> Option<u16> {
+        let mut value: u16 = 0;
+
+        for (subtract, term) in &expression.terms {
+            let term = match term {
+                Term::Number(number) => *number,
+                Term::Label(label) => *self.symbols.get(label)?,
+            };
+
+            value = if *subtract {
+                value.wrapping_sub(term)
+            } else {
+                value.wrapping_add(term)
+            };
+        }
+
+        Some(match expression.part {
+            BytePart::Whole => value,
+            BytePart::Low => value & 0xFF,
+            BytePart::High => value >> 8,
+        })
+    }
+
+    fn resolve(&self, expression: &Expression) -> Result<u16, String> {
+        self.evaluate(expression)
+            .ok_or_else(|| format!("{} is not defined", expression.describe()))
+    }
+
+    fn resolve_byte(&self, expression: &Expression) -> Result<u8, String> {
+        let value = self.resolve(expression)?;
+
+        u8::try_from(value).map_err(|_| format!("${:04X} doesn't fit in a byte", value))
+    }
+
+    // Second pass, all the labels are known so the operands can be filled in
+    fn emit(self) -> Result<Vec<u8>, AssemblyError> {
+        let mut image = vec![0; PRG_SIZE];
+
+        let [low, high] = PRG_START.to_le_bytes();
+        image[offset(RESET_VECTOR)] = low;
+        image[offset(RESET_VECTOR) + 1] = high;
+
+        for statement in &self.statements {
+            let bytes = self
+                .statement_bytes(statement)
+                .map_err(|message| AssemblyError {
+                    line: statement.line,
+                    message,
+                })?;
+
+            let start = offset(statement.addr);
+            image[start..start + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        for (vector, expression, line) in &self.vectors {
+            let [low, high] = self
+                .resolve(expression)
+                .map_err(|message| AssemblyError {
+                    line: *line,
+                    message,
+                })?
+                .to_le_bytes();
+
+            image[offset(*vector)] = low;
+            image[offset(*vector) + 1] = high;
+        }
+
+        Ok(image)
+    }
+
+    fn statement_bytes(&self, statement: &Statement) -> Result<Vec<u8>, String> {
+        match &statement.data {
+            Data::Bytes(values) => values
+                .iter()
+                .map(|value| self.resolve_byte(value))
+                .collect(),
+            Data::Words(values) => {
+                let mut bytes = vec![];
+
+                for value in values {
+                    bytes.extend(self.resolve(value)?.to_le_bytes());
+                }
+
+                Ok(bytes)
+            }
+            Data::Instruction {
+                opcode,
+                mode,
+                operand,
+            } => {
+                let mut bytes = vec![*opcode];
+
+                let Some(operand) = operand else {
+                    return Ok(bytes);
+                };
+
+                match mode {
+                    AddrMode::Relative => {
+                        let target = self.resolve(operand)? as i32;
+                        let distance = target - (statement.addr as i32 + 2);
+
+                        let distance = i8::try_from(distance)
+                            .map_err(|_| format!("branch to ${:04X} is out of range", target))?;
+
+                        bytes.push(distance as u8);
+                    }
+                    AddrMode::Absolute
+                    | AddrMode::AbsoluteX
+                    | AddrMode::AbsoluteY
+                    | AddrMode::Indirect => bytes.extend(self.resolve(operand)?.to_le_bytes()),
+                    _ => bytes.push(self.resolve_byte(operand)?),
+                }
+
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+impl Expression {
+    fn describe(&self) -> String {
+        self.terms
+            .iter()
+            .find_map(|(_, term)| match term {
+                Term::Label(label) => Some(label.clone()),
+                Term::Number(_) => None,
+            })
+            .unwrap_or_else(|| "expression".to_string())
+    }
+}
+
+fn offset(addr: u16) -> usize {
+    (addr - PRG_START) as usize
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn check_identifier(name: &str) -> Result<(), String> {
+    if is_identifier(name) {
+        Ok(())
+    } else {
+        Err(format!("{} is not a valid name", name))
+    }
+}
+
+fn is_zero_page(mode: &AddrMode) -> bool {
+    matches!(
+        mode,
+        AddrMode::ZeroPage | AddrMode::ZeroPageX | AddrMode::ZeroPageY
+    )
+}
+
+// Works out which addressing modes the operand could be for, most specific
+// first, and the expression in it
+fn parse_operand(operand: &str) -> Result<(Vec<AddrMode>, Option<Expression>), String> {
+    use AddrMode::*;
+
+    let operand: String = operand.chars().filter(|c| !c.is_whitespace()).collect();
+    let upper = operand.to_ascii_uppercase();
+
+    let (modes, start, end) = if operand.is_empty() {
+        return Ok((vec![Implied, Accumulator], None));
+    } else if upper == "A" {
+        return Ok((vec![Accumulator], None));
+    } else if upper.starts_with('#') {
+        (vec![Immediate], 1, 0)
+    } else if upper.starts_with('(') && upper.ends_with(",X)") {
+        (vec![IndirectX], 1, 3)
+    } else if upper.starts_with('(') && upper.ends_with("),Y") {
+        (vec![IndirectY], 1, 3)
+    } else if upper.starts_with('(') && upper.ends_with(')') {
+        (vec![Indirect], 1, 1)
+    } else if upper.ends_with(",X") {
+        (vec![ZeroPageX, AbsoluteX], 0, 2)
+    } else if upper.ends_with(",Y") {
+        (vec![ZeroPageY, AbsoluteY], 0, 2)
+    } else {
+        (vec![Relative, ZeroPage, Absolute], 0, 0)
+    };
+
+    let expression = operand
+        .get(start..operand.len() - end)
+        .ok_or_else(|| format!("can't read the operand {}", operand))?;
+
+    Ok((modes, Some(parse_expression(expression)?)))
+}
+
+fn parse_expression(text: &str) -> Result<Expression, String> {
+    let (part, text) = match text.chars().next() {
+        Some('<') => (BytePart::Low, &text[1..]),
+        Some('>') => (BytePart::High, &text[1..]),
+        _ => (BytePart::Whole, text),
+    };
+
+    let mut terms = vec![];
+    let mut subtract = false;
+    let mut rest = text.trim();
+
+    loop {
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        let term = rest[..end].trim();
+
+        terms.push((subtract, parse_term(term)?));
+
+        if end == rest.len() {
+            break;
+        }
+
+        subtract = rest[end..].starts_with('-');
+        rest = &rest[end + 1..];
+    }
+
+    Ok(Expression { part, terms })
+}
+
+fn parse_term(term: &str) -> Result<Term, String> {
+    let number = if let Some(hex) = term.strip_prefix('$') {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(binary) = term.strip_prefix('%') {
+        u16::from_str_radix(binary, 2)
+    } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+        term.parse()
+    } else {
+        check_identifier(term)?;
+
+        return Ok(Term::Label(term.to_string()));
+    };
+
+    number
+        .map(Term::Number)
+        .map_err(|_| format!("{} is not a valid number", term))
+}
+
+// Legal opcodes win over illegal ones with the same name and mode
+fn find_opcode(mnemonic: &str, mode: impl Fn(&AddrMode) -> bool) -> Option<u8> {
+    let matches = |opcode: &usize| {
+        let instruction = &OPCODES[*opcode];
+
+        instruction.name == mnemonic && mode(&instruction.mode)
+    };
+
+    (0..256)
+        .filter(matches)
+        .find(|opcode| OPCODES[*opcode].legal)
+        .or_else(|| (0..256).find(matches))
+        .map(|opcode| opcode as u8)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::NES;
+
+    // The bytes assembled at $8000
+    #[allow(dead_code)]
+    fn code(source: &str) -> Vec<u8> {
+        let image = assemble(source).unwrap();
+        let end = image[..offset(RESET_VECTOR)]
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |last| last + 1);
+
+        image[..end].to_vec()
+    }
+
+    #[allow(dead_code)]
+    fn error(source: &str) -> AssemblyError {
+        assemble(source).unwrap_err()
+    }
+
+    #[test]
+    fn assembles_every_addressing_mode() {
+        let source = "
+            INX
+            ASL
+            ROR A
+            LDA #$12
+            LDA $34
+            LDA $34,X
+            LDX $34,Y
+            LDA $1234
+            LDA $1234,X
+            LDA $1234,Y
+            JMP ($1234)
+            LDA ($34,X)
+            LDA ($34),Y
+        here:
+            BNE here
+        ";
+
+        assert_eq!(
+            code(source),
+            [
+                0xE8, 0x0A, 0x6A, 0xA9, 0x12, 0xA5, 0x34, 0xB5, 0x34, 0xB6, 0x34, 0xAD, 0x34, 0x12,
+                0xBD, 0x34, 0x12, 0xB9, 0x34, 0x12, 0x6C, 0x34, 0x12, 0xA1, 0x34, 0xB1, 0x34, 0xD0,
+                0xFE,
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_page_falls_back_to_absolute() {
+        // LDA has no zero page,Y so it uses absolute,Y
+        assert_eq!(code("lda $34,y\nnop"), [0xB9, 0x34, 0x00, 0xEA]);
+        assert_eq!(code("LDA $0034"), [0xA5, 0x34]);
+    }
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        let source = "
+            JMP end
+            BEQ end
+        end: JSR end
+        ";
+
+        assert_eq!(
+            code(source),
+            [0x4C, 0x05, 0x80, 0xF0, 0x00, 0x20, 0x05, 0x80]
+        );
+    }
+
+    #[test]
+    fn constants_and_expressions() {
+        let source = "
+            PPUCTRL = $2000
+            counter = $10
+            STA PPUCTRL
+            INC counter+1
+            LDA #<table
+            LDX #>table
+            LDA table-1,Y
+        table: .byte 1, $02, %11
+            .word table, $1234
+        ";
+
+        assert_eq!(
+            code(source),
+            [
+                0x8D, 0x00, 0x20, 0xE6, 0x11, 0xA9, 0x0C, 0xA2, 0x80, 0xB9, 0x0B, 0x80, 0x01, 0x02,
+                0x03, 0x0C, 0x80, 0x34, 0x12,
+            ]
+        );
+    }
+
+    #[test]
+    fn org_and_vectors() {
+        let source = "
+            .reset start
+            .nmi nmi
+            .irq irq
+            .org $9000
+        start: NOP
+        nmi: RTI
+        irq: RTI
+        ";
+        let image = assemble(source).unwrap();
+
+        assert_eq!(image.len(), 0x8000);
+        assert_eq!(image[0x1000..0x1003], [0xEA, 0x40, 0x40]);
+        assert_eq!(image[0x7FFA..], [0x01, 0x90, 0x00, 0x90, 0x02, 0x90]);
+    }
+
+    #[test]
+    fn reset_vector_defaults_to_prg_start() {
+        let image = assemble("NOP").unwrap();
+
+        assert_eq!(image[0x7FFC..0x7FFE], [0x00, 0x80]);
+    }
+
+    #[test]
+    fn legal_opcodes_are_preferred() {
+        // $EB is an illegal copy of SBC #
+        assert_eq!(code("SBC #$01"), [0xE9, 0x01]);
+        assert_eq!(code("LAX $10"), [0xA7, 0x10]);
+    }
+
+    #[test]
+    fn comments_and_case_are_ignored() {
+        assert_eq!(
+            code("  lda #$01 ; load one\n; nothing\n  Rts"),
+            [0xA9, 0x01, 0x60]
+        );
+    }
+
+    #[test]
+    fn errors_report_the_line() {
+        assert_eq!(
+            error("NOP\nFOO"),
+            AssemblyError {
+                line: 2,
+                message: "unknown instruction FOO".to_string()
+            }
+        );
+        assert_eq!(error("NOP\n\nJMP nowhere").line, 3);
+        assert_eq!(
+            error("STA #$01").message,
+            "STA can't take the operand \"#$01\""
+        );
+        assert_eq!(error("LDA #$100").message, "$0100 doesn't fit in a byte");
+        assert_eq!(error("a: NOP\na: NOP").message, "a is already defined");
+    }
+
+    #[test]
+    fn branches_must_be_in_range() {
+        let source = "
+            BEQ far
+            .org $8100
+        far: NOP
+        ";
+
+        assert_eq!(error(source).message, "branch to $8100 is out of range");
+    }
+
+    #[test]
+    fn org_cant_overwrite_code() {
+        let source = "
+            NOP
+            LDA #$01
+            .org $8002
+            NOP
+        ";
+
+        assert_eq!(
+            error(source),
+            AssemblyError {
+                line: 5,
+                message: "$8002 overlaps the bytes from line 3".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn runs_on_the_nes() {
+        let program = assemble(
+            "
+            LDX #3
+        loop:
+            DEX
+            BNE loop
+            STX $10
+        ",
+        )
+        .unwrap();
+
+        let mut nes = NES::from_cartridge(crate::cartridge::test::test_rom(Some(program)));
+        nes.cpu_ram[0x10] = 0xFF;
+
+        for _ in 0..8 {
+            nes.step_instruction();
+        }
+
+        assert_eq!(nes.cpu_ram[0x10], 0);
+    }
+}
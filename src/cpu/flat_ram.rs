@@ -0,0 +1,44 @@
+use alloc::{vec, vec::Vec};
+
+use super::Bus;
+
+// 64KB of RAM and nothing else, for running the 6502 on its own
+pub struct FlatRam {
+    pub memory: Vec<u8>,
+    pub cycles: usize,
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self {
+            memory: vec![0; 0x10000],
+            cycles: 0,
+        }
+    }
+}
+
+impl FlatRam {
+    pub fn load(&mut self, addr: u16, data: &[u8]) {
+        let start = addr as usize;
+
+        self.memory[start..start + data.len()].copy_from_slice(data);
+    }
+}
+
+impl Bus for FlatRam {
+    fn tick(&mut self) {
+        self.cycles += 1;
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+}
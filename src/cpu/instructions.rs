@@ -1,6 +1,6 @@
-use crate::NES;
+use core::marker::PhantomData;
 
-use super::{AddrMode, CPU};
+use super::{flat_ram::FlatRam, AddrMode, Bus, Cpu};
 
 macro_rules! instr {
     ($name: expr, $mode: expr, $cycles: expr, $len: expr, $fn: expr) => {
@@ -28,1147 +28,1350 @@ macro_rules! il_instr {
     };
 }
 
-pub(crate) const INSTRUCTIONS_TABLE: [Instruction; 256] = [
-    instr!("BRK", AddrMode::Implied, 7, 1, brk),
-    instr!("ORA", AddrMode::IndirectX, 6, 2, ora),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil), // This technically is not correct, KIL explodes the CPU but we'll treat it as a NOP
-    il_instr!("SLO", AddrMode::IndirectX, 8, 2, slo),
-    il_instr!("DOP", AddrMode::ZeroPage, 3, 2, dop),
-    instr!("ORA", AddrMode::ZeroPage, 3, 2, ora),
-    instr!("ASL", AddrMode::ZeroPage, 5, 2, asl),
-    il_instr!("SLO", AddrMode::ZeroPage, 5, 2, slo),
-    instr!("PHP", AddrMode::Implied, 3, 1, php),
-    instr!("ORA", AddrMode::Immediate, 2, 2, ora),
-    instr!("ASL", AddrMode::Accumulator, 2, 1, asl),
-    il_instr!("ANC", AddrMode::Immediate, 2, 2, anc),
-    il_instr!("TOP", AddrMode::Absolute, 4, 3, top),
-    instr!("ORA", AddrMode::Absolute, 4, 3, ora),
-    instr!("ASL", AddrMode::Absolute, 6, 3, asl),
-    il_instr!("SLO", AddrMode::Absolute, 6, 3, slo),
-    instr!("BPL", AddrMode::Relative, 2, 2, bpl),
-    instr!("ORA", AddrMode::IndirectY, 5, 2, ora),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("SLO", AddrMode::IndirectY, 8, 2, slo),
-    il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
-    instr!("ORA", AddrMode::ZeroPageX, 4, 2, ora),
-    instr!("ASL", AddrMode::ZeroPageX, 6, 2, asl),
-    il_instr!("SLO", AddrMode::ZeroPageX, 6, 2, slo),
-    instr!("CLC", AddrMode::Implied, 2, 1, clc),
-    instr!("ORA", AddrMode::AbsoluteY, 4, 3, ora),
-    il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
-    il_instr!("SLO", AddrMode::AbsoluteY, 7, 3, slo),
-    il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
-    instr!("ORA", AddrMode::AbsoluteX, 4, 3, ora),
-    instr!("ASL", AddrMode::AbsoluteX, 7, 3, asl),
-    il_instr!("SLO", AddrMode::AbsoluteX, 7, 3, slo),
-    instr!("JSR", AddrMode::Absolute, 6, 3, jsr),
-    instr!("AND", AddrMode::IndirectX, 6, 2, and),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("RLA", AddrMode::IndirectX, 8, 2, rla),
-    instr!("BIT", AddrMode::ZeroPage, 3, 2, bit),
-    instr!("AND", AddrMode::ZeroPage, 3, 2, and),
-    instr!("ROL", AddrMode::ZeroPage, 5, 2, rol),
-    il_instr!("RLA", AddrMode::ZeroPage, 5, 2, rla),
-    instr!("PLP", AddrMode::Implied, 4, 1, plp),
-    instr!("AND", AddrMode::Immediate, 2, 2, and),
-    instr!("ROL", AddrMode::Accumulator, 2, 1, rol),
-    il_instr!("ANC", AddrMode::Immediate, 2, 2, anc),
-    instr!("BIT", AddrMode::Absolute, 4, 3, bit),
-    instr!("AND", AddrMode::Absolute, 4, 3, and),
-    instr!("ROL", AddrMode::Absolute, 6, 3, rol),
-    il_instr!("RLA", AddrMode::Absolute, 6, 3, rla),
-    instr!("BMI", AddrMode::Relative, 2, 2, bmi),
-    instr!("AND", AddrMode::IndirectY, 5, 2, and),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("RLA", AddrMode::IndirectY, 8, 2, rla),
-    il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
-    instr!("AND", AddrMode::ZeroPageX, 4, 2, and),
-    instr!("ROL", AddrMode::ZeroPageX, 6, 2, rol),
-    il_instr!("RLA", AddrMode::ZeroPageX, 6, 2, rla),
-    instr!("SEC", AddrMode::Implied, 2, 1, sec),
-    instr!("AND", AddrMode::AbsoluteY, 4, 3, and),
-    il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
-    il_instr!("RLA", AddrMode::AbsoluteY, 7, 3, rla),
-    il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
-    instr!("AND", AddrMode::AbsoluteX, 4, 3, and),
-    instr!("ROL", AddrMode::AbsoluteX, 7, 3, rol),
-    il_instr!("RLA", AddrMode::AbsoluteX, 7, 3, rla),
-    instr!("RTI", AddrMode::Implied, 6, 1, rti),
-    instr!("EOR", AddrMode::IndirectX, 6, 2, eor),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("SRE", AddrMode::IndirectX, 8, 2, sre),
-    il_instr!("DOP", AddrMode::ZeroPage, 3, 2, dop),
-    instr!("EOR", AddrMode::ZeroPage, 3, 2, eor),
-    instr!("LSR", AddrMode::ZeroPage, 5, 2, lsr),
-    il_instr!("SRE", AddrMode::ZeroPage, 5, 2, sre),
-    instr!("PHA", AddrMode::Implied, 3, 1, pha),
-    instr!("EOR", AddrMode::Immediate, 2, 2, eor),
-    instr!("LSR", AddrMode::Accumulator, 2, 1, lsr),
-    il_instr!("ASR", AddrMode::Immediate, 2, 2, asr),
-    instr!("JMP", AddrMode::Absolute, 3, 3, jmp),
-    instr!("EOR", AddrMode::Absolute, 4, 3, eor),
-    instr!("LSR", AddrMode::Absolute, 6, 3, lsr),
-    il_instr!("SRE", AddrMode::Absolute, 6, 3, sre),
-    instr!("BVC", AddrMode::Relative, 2, 2, bvc),
-    instr!("EOR", AddrMode::IndirectY, 5, 2, eor),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("SRE", AddrMode::IndirectY, 8, 2, sre),
-    il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
-    instr!("EOR", AddrMode::ZeroPageX, 4, 2, eor),
-    instr!("LSR", AddrMode::ZeroPageX, 6, 2, lsr),
-    il_instr!("SRE", AddrMode::ZeroPageX, 6, 2, sre),
-    instr!("CLI", AddrMode::Implied, 2, 1, cli),
-    instr!("EOR", AddrMode::AbsoluteY, 4, 3, eor),
-    il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
-    il_instr!("SRE", AddrMode::AbsoluteY, 7, 3, sre),
-    il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
-    instr!("EOR", AddrMode::AbsoluteX, 4, 3, eor),
-    instr!("LSR", AddrMode::AbsoluteX, 7, 3, lsr),
-    il_instr!("SRE", AddrMode::AbsoluteX, 7, 3, sre),
-    instr!("RTS", AddrMode::Implied, 6, 1, rts),
-    instr!("ADC", AddrMode::IndirectX, 6, 2, adc),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("RRA", AddrMode::IndirectX, 8, 2, rra),
-    il_instr!("DOP", AddrMode::ZeroPage, 3, 2, dop),
-    instr!("ADC", AddrMode::ZeroPage, 3, 2, adc),
-    instr!("ROR", AddrMode::ZeroPage, 5, 2, ror),
-    il_instr!("RRA", AddrMode::ZeroPage, 5, 2, rra),
-    instr!("PLA", AddrMode::Implied, 4, 1, pla),
-    instr!("ADC", AddrMode::Immediate, 2, 2, adc),
-    instr!("ROR", AddrMode::Accumulator, 2, 1, ror),
-    il_instr!("ARR", AddrMode::Immediate, 2, 2, arr),
-    instr!("JMP", AddrMode::Indirect, 5, 3, jmp),
-    instr!("ADC", AddrMode::Absolute, 4, 3, adc),
-    instr!("ROR", AddrMode::Absolute, 6, 3, ror),
-    il_instr!("RRA", AddrMode::Absolute, 6, 3, rra),
-    instr!("BVS", AddrMode::Relative, 2, 2, bvs),
-    instr!("ADC", AddrMode::IndirectY, 5, 2, adc),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("RRA", AddrMode::IndirectY, 8, 2, rra),
-    il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
-    instr!("ADC", AddrMode::ZeroPageX, 4, 2, adc),
-    instr!("ROR", AddrMode::ZeroPageX, 6, 2, ror),
-    il_instr!("RRA", AddrMode::ZeroPageX, 6, 2, rra),
-    instr!("SEI", AddrMode::Implied, 2, 1, sei),
-    instr!("ADC", AddrMode::AbsoluteY, 4, 3, adc),
-    il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
-    il_instr!("RRA", AddrMode::AbsoluteY, 7, 3, rra),
-    il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
-    instr!("ADC", AddrMode::AbsoluteX, 4, 3, adc),
-    instr!("ROR", AddrMode::AbsoluteX, 7, 3, ror),
-    il_instr!("RRA", AddrMode::AbsoluteX, 7, 3, rra),
-    il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
-    instr!("STA", AddrMode::IndirectX, 6, 2, sta),
-    il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
-    il_instr!("SAX", AddrMode::IndirectX, 6, 2, sax),
-    instr!("STY", AddrMode::ZeroPage, 3, 2, sty),
-    instr!("STA", AddrMode::ZeroPage, 3, 2, sta),
-    instr!("STX", AddrMode::ZeroPage, 3, 2, stx),
-    il_instr!("SAX", AddrMode::ZeroPage, 3, 2, sax),
-    instr!("DEY", AddrMode::Implied, 2, 1, dey),
-    il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
-    instr!("TXA", AddrMode::Implied, 2, 1, txa),
-    il_instr!("XAA", AddrMode::Immediate, 2, 2, xaa),
-    instr!("STY", AddrMode::Absolute, 4, 3, sty),
-    instr!("STA", AddrMode::Absolute, 4, 3, sta),
-    instr!("STX", AddrMode::Absolute, 4, 3, stx),
-    il_instr!("SAX", AddrMode::Absolute, 4, 3, sax),
-    instr!("BCC", AddrMode::Relative, 2, 2, bcc),
-    instr!("STA", AddrMode::IndirectY, 6, 2, sta),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("AXA", AddrMode::IndirectY, 6, 2, axa),
-    instr!("STY", AddrMode::ZeroPageX, 4, 2, sty),
-    instr!("STA", AddrMode::ZeroPageX, 4, 2, sta),
-    instr!("STX", AddrMode::ZeroPageY, 4, 2, stx),
-    il_instr!("SAX", AddrMode::ZeroPageY, 4, 2, sax),
-    instr!("TYA", AddrMode::Implied, 2, 1, tya),
-    instr!("STA", AddrMode::AbsoluteY, 5, 3, sta),
-    instr!("TXS", AddrMode::Implied, 2, 1, txs),
-    il_instr!("XAS", AddrMode::AbsoluteY, 5, 3, xas),
-    il_instr!("SYA", AddrMode::AbsoluteX, 5, 3, sya),
-    instr!("STA", AddrMode::AbsoluteX, 5, 3, sta),
-    il_instr!("SXA", AddrMode::AbsoluteY, 5, 3, sxa),
-    il_instr!("AXA", AddrMode::AbsoluteY, 5, 3, axa),
-    instr!("LDY", AddrMode::Immediate, 2, 2, ldy),
-    instr!("LDA", AddrMode::IndirectX, 6, 2, lda),
-    instr!("LDX", AddrMode::Immediate, 2, 2, ldx),
-    il_instr!("LAX", AddrMode::IndirectX, 6, 2, lax),
-    instr!("LDY", AddrMode::ZeroPage, 3, 2, ldy),
-    instr!("LDA", AddrMode::ZeroPage, 3, 2, lda),
-    instr!("LDX", AddrMode::ZeroPage, 3, 2, ldx),
-    il_instr!("LAX", AddrMode::ZeroPage, 3, 2, lax),
-    instr!("TAY", AddrMode::Implied, 2, 1, tay),
-    instr!("LDA", AddrMode::Immediate, 2, 2, lda),
-    instr!("TAX", AddrMode::Implied, 2, 1, tax),
-    il_instr!("LXA", AddrMode::Immediate, 2, 2, lxa),
-    instr!("LDY", AddrMode::Absolute, 4, 3, ldy),
-    instr!("LDA", AddrMode::Absolute, 4, 3, lda),
-    instr!("LDX", AddrMode::Absolute, 4, 3, ldx),
-    il_instr!("LAX", AddrMode::Absolute, 4, 3, lax),
-    instr!("BCS", AddrMode::Relative, 2, 2, bcs),
-    instr!("LDA", AddrMode::IndirectY, 5, 2, lda),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("LAX", AddrMode::IndirectY, 5, 2, lax),
-    instr!("LDY", AddrMode::ZeroPageX, 4, 2, ldy),
-    instr!("LDA", AddrMode::ZeroPageX, 4, 2, lda),
-    instr!("LDX", AddrMode::ZeroPageY, 4, 2, ldx),
-    il_instr!("LAX", AddrMode::ZeroPageY, 4, 2, lax),
-    instr!("CLV", AddrMode::Implied, 2, 1, clv),
-    instr!("LDA", AddrMode::AbsoluteY, 4, 3, lda),
-    instr!("TSX", AddrMode::Implied, 2, 1, tsx),
-    il_instr!("LAS", AddrMode::AbsoluteY, 4, 3, las),
-    instr!("LDY", AddrMode::AbsoluteX, 4, 3, ldy),
-    instr!("LDA", AddrMode::AbsoluteX, 4, 3, lda),
-    instr!("LDX", AddrMode::AbsoluteY, 4, 3, ldx),
-    il_instr!("LAX", AddrMode::AbsoluteY, 4, 3, lax),
-    instr!("CPY", AddrMode::Immediate, 2, 2, cpy),
-    instr!("CMP", AddrMode::IndirectX, 6, 2, cmp),
-    il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
-    il_instr!("DCP", AddrMode::IndirectX, 8, 2, dcp),
-    instr!("CPY", AddrMode::ZeroPage, 3, 2, cpy),
-    instr!("CMP", AddrMode::ZeroPage, 3, 2, cmp),
-    instr!("DEC", AddrMode::ZeroPage, 5, 2, dec),
-    il_instr!("DCP", AddrMode::ZeroPage, 5, 2, dcp),
-    instr!("INY", AddrMode::Implied, 2, 1, iny),
-    instr!("CMP", AddrMode::Immediate, 2, 2, cmp),
-    instr!("DEX", AddrMode::Implied, 2, 1, dex),
-    il_instr!("AXS", AddrMode::Immediate, 2, 2, axs),
-    instr!("CPY", AddrMode::Absolute, 4, 3, cpy),
-    instr!("CMP", AddrMode::Absolute, 4, 3, cmp),
-    instr!("DEC", AddrMode::Absolute, 6, 3, dec),
-    il_instr!("DCP", AddrMode::Absolute, 6, 3, dcp),
-    instr!("BNE", AddrMode::Relative, 2, 2, bne),
-    instr!("CMP", AddrMode::IndirectY, 5, 2, cmp),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("DCP", AddrMode::IndirectY, 8, 2, dcp),
-    il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
-    instr!("CMP", AddrMode::ZeroPageX, 4, 2, cmp),
-    instr!("DEC", AddrMode::ZeroPageX, 6, 2, dec),
-    il_instr!("DCP", AddrMode::ZeroPageX, 6, 2, dcp),
-    instr!("CLD", AddrMode::Implied, 2, 1, cld),
-    instr!("CMP", AddrMode::AbsoluteY, 4, 3, cmp),
-    il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
-    il_instr!("DCP", AddrMode::AbsoluteY, 7, 3, dcp),
-    il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
-    instr!("CMP", AddrMode::AbsoluteX, 4, 3, cmp),
-    instr!("DEC", AddrMode::AbsoluteX, 7, 3, dec),
-    il_instr!("DCP", AddrMode::AbsoluteX, 7, 3, dcp),
-    instr!("CPX", AddrMode::Immediate, 2, 2, cpx),
-    instr!("SBC", AddrMode::IndirectX, 6, 2, sbc),
-    il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
-    il_instr!("ISB", AddrMode::IndirectX, 8, 2, isb),
-    instr!("CPX", AddrMode::ZeroPage, 3, 2, cpx),
-    instr!("SBC", AddrMode::ZeroPage, 3, 2, sbc),
-    instr!("INC", AddrMode::ZeroPage, 5, 2, inc),
-    il_instr!("ISB", AddrMode::ZeroPage, 5, 2, isb),
-    instr!("INX", AddrMode::Implied, 2, 1, inx),
-    instr!("SBC", AddrMode::Immediate, 2, 2, sbc),
-    instr!("NOP", AddrMode::Implied, 2, 1, nop),
-    il_instr!("SBC", AddrMode::Immediate, 2, 2, sbc),
-    instr!("CPX", AddrMode::Absolute, 4, 3, cpx),
-    instr!("SBC", AddrMode::Absolute, 4, 3, sbc),
-    instr!("INC", AddrMode::Absolute, 6, 3, inc),
-    il_instr!("ISB", AddrMode::Absolute, 6, 3, isb),
-    instr!("BEQ", AddrMode::Relative, 2, 2, beq),
-    instr!("SBC", AddrMode::IndirectY, 5, 2, sbc),
-    il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
-    il_instr!("ISB", AddrMode::IndirectY, 8, 2, isb),
-    il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
-    instr!("SBC", AddrMode::ZeroPageX, 4, 2, sbc),
-    instr!("INC", AddrMode::ZeroPageX, 6, 2, inc),
-    il_instr!("ISB", AddrMode::ZeroPageX, 6, 2, isb),
-    instr!("SED", AddrMode::Implied, 2, 1, sed),
-    instr!("SBC", AddrMode::AbsoluteY, 4, 3, sbc),
-    il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
-    il_instr!("ISB", AddrMode::AbsoluteY, 7, 3, isb),
-    il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
-    instr!("SBC", AddrMode::AbsoluteX, 4, 3, sbc),
-    instr!("INC", AddrMode::AbsoluteX, 7, 3, inc),
-    il_instr!("ISB", AddrMode::AbsoluteX, 7, 3, isb),
-];
-
-pub struct Instruction {
+// The table is built for each bus so the instructions can be generic over it
+// without going through a trait object
+pub struct Instructions<B>(PhantomData<B>);
+
+impl<B: Bus> Instructions<B> {
+    pub const TABLE: [Instruction<B>; 256] = [
+        instr!("BRK", AddrMode::Implied, 7, 1, brk),
+        instr!("ORA", AddrMode::IndirectX, 6, 2, ora),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("SLO", AddrMode::IndirectX, 8, 2, slo),
+        il_instr!("DOP", AddrMode::ZeroPage, 3, 2, dop),
+        instr!("ORA", AddrMode::ZeroPage, 3, 2, ora),
+        instr!("ASL", AddrMode::ZeroPage, 5, 2, asl),
+        il_instr!("SLO", AddrMode::ZeroPage, 5, 2, slo),
+        instr!("PHP", AddrMode::Implied, 3, 1, php),
+        instr!("ORA", AddrMode::Immediate, 2, 2, ora),
+        instr!("ASL", AddrMode::Accumulator, 2, 1, asl),
+        il_instr!("ANC", AddrMode::Immediate, 2, 2, anc),
+        il_instr!("TOP", AddrMode::Absolute, 4, 3, top),
+        instr!("ORA", AddrMode::Absolute, 4, 3, ora),
+        instr!("ASL", AddrMode::Absolute, 6, 3, asl),
+        il_instr!("SLO", AddrMode::Absolute, 6, 3, slo),
+        instr!("BPL", AddrMode::Relative, 2, 2, bpl),
+        instr!("ORA", AddrMode::IndirectY, 5, 2, ora),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("SLO", AddrMode::IndirectY, 8, 2, slo),
+        il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
+        instr!("ORA", AddrMode::ZeroPageX, 4, 2, ora),
+        instr!("ASL", AddrMode::ZeroPageX, 6, 2, asl),
+        il_instr!("SLO", AddrMode::ZeroPageX, 6, 2, slo),
+        instr!("CLC", AddrMode::Implied, 2, 1, clc),
+        instr!("ORA", AddrMode::AbsoluteY, 4, 3, ora),
+        il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
+        il_instr!("SLO", AddrMode::AbsoluteY, 7, 3, slo),
+        il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
+        instr!("ORA", AddrMode::AbsoluteX, 4, 3, ora),
+        instr!("ASL", AddrMode::AbsoluteX, 7, 3, asl),
+        il_instr!("SLO", AddrMode::AbsoluteX, 7, 3, slo),
+        instr!("JSR", AddrMode::Absolute, 6, 3, jsr),
+        instr!("AND", AddrMode::IndirectX, 6, 2, and),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("RLA", AddrMode::IndirectX, 8, 2, rla),
+        instr!("BIT", AddrMode::ZeroPage, 3, 2, bit),
+        instr!("AND", AddrMode::ZeroPage, 3, 2, and),
+        instr!("ROL", AddrMode::ZeroPage, 5, 2, rol),
+        il_instr!("RLA", AddrMode::ZeroPage, 5, 2, rla),
+        instr!("PLP", AddrMode::Implied, 4, 1, plp),
+        instr!("AND", AddrMode::Immediate, 2, 2, and),
+        instr!("ROL", AddrMode::Accumulator, 2, 1, rol),
+        il_instr!("ANC", AddrMode::Immediate, 2, 2, anc),
+        instr!("BIT", AddrMode::Absolute, 4, 3, bit),
+        instr!("AND", AddrMode::Absolute, 4, 3, and),
+        instr!("ROL", AddrMode::Absolute, 6, 3, rol),
+        il_instr!("RLA", AddrMode::Absolute, 6, 3, rla),
+        instr!("BMI", AddrMode::Relative, 2, 2, bmi),
+        instr!("AND", AddrMode::IndirectY, 5, 2, and),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("RLA", AddrMode::IndirectY, 8, 2, rla),
+        il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
+        instr!("AND", AddrMode::ZeroPageX, 4, 2, and),
+        instr!("ROL", AddrMode::ZeroPageX, 6, 2, rol),
+        il_instr!("RLA", AddrMode::ZeroPageX, 6, 2, rla),
+        instr!("SEC", AddrMode::Implied, 2, 1, sec),
+        instr!("AND", AddrMode::AbsoluteY, 4, 3, and),
+        il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
+        il_instr!("RLA", AddrMode::AbsoluteY, 7, 3, rla),
+        il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
+        instr!("AND", AddrMode::AbsoluteX, 4, 3, and),
+        instr!("ROL", AddrMode::AbsoluteX, 7, 3, rol),
+        il_instr!("RLA", AddrMode::AbsoluteX, 7, 3, rla),
+        instr!("RTI", AddrMode::Implied, 6, 1, rti),
+        instr!("EOR", AddrMode::IndirectX, 6, 2, eor),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("SRE", AddrMode::IndirectX, 8, 2, sre),
+        il_instr!("DOP", AddrMode::ZeroPage, 3, 2, dop),
+        instr!("EOR", AddrMode::ZeroPage, 3, 2, eor),
+        instr!("LSR", AddrMode::ZeroPage, 5, 2, lsr),
+        il_instr!("SRE", AddrMode::ZeroPage, 5, 2, sre),
+        instr!("PHA", AddrMode::Implied, 3, 1, pha),
+        instr!("EOR", AddrMode::Immediate, 2, 2, eor),
+        instr!("LSR", AddrMode::Accumulator, 2, 1, lsr),
+        il_instr!("ASR", AddrMode::Immediate, 2, 2, asr),
+        instr!("JMP", AddrMode::Absolute, 3, 3, jmp),
+        instr!("EOR", AddrMode::Absolute, 4, 3, eor),
+        instr!("LSR", AddrMode::Absolute, 6, 3, lsr),
+        il_instr!("SRE", AddrMode::Absolute, 6, 3, sre),
+        instr!("BVC", AddrMode::Relative, 2, 2, bvc),
+        instr!("EOR", AddrMode::IndirectY, 5, 2, eor),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("SRE", AddrMode::IndirectY, 8, 2, sre),
+        il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
+        instr!("EOR", AddrMode::ZeroPageX, 4, 2, eor),
+        instr!("LSR", AddrMode::ZeroPageX, 6, 2, lsr),
+        il_instr!("SRE", AddrMode::ZeroPageX, 6, 2, sre),
+        instr!("CLI", AddrMode::Implied, 2, 1, cli),
+        instr!("EOR", AddrMode::AbsoluteY, 4, 3, eor),
+        il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
+        il_instr!("SRE", AddrMode::AbsoluteY, 7, 3, sre),
+        il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
+        instr!("EOR", AddrMode::AbsoluteX, 4, 3, eor),
+        instr!("LSR", AddrMode::AbsoluteX, 7, 3, lsr),
+        il_instr!("SRE", AddrMode::AbsoluteX, 7, 3, sre),
+        instr!("RTS", AddrMode::Implied, 6, 1, rts),
+        instr!("ADC", AddrMode::IndirectX, 6, 2, adc),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("RRA", AddrMode::IndirectX, 8, 2, rra),
+        il_instr!("DOP", AddrMode::ZeroPage, 3, 2, dop),
+        instr!("ADC", AddrMode::ZeroPage, 3, 2, adc),
+        instr!("ROR", AddrMode::ZeroPage, 5, 2, ror),
+        il_instr!("RRA", AddrMode::ZeroPage, 5, 2, rra),
+        instr!("PLA", AddrMode::Implied, 4, 1, pla),
+        instr!("ADC", AddrMode::Immediate, 2, 2, adc),
+        instr!("ROR", AddrMode::Accumulator, 2, 1, ror),
+        il_instr!("ARR", AddrMode::Immediate, 2, 2, arr),
+        instr!("JMP", AddrMode::Indirect, 5, 3, jmp),
+        instr!("ADC", AddrMode::Absolute, 4, 3, adc),
+        instr!("ROR", AddrMode::Absolute, 6, 3, ror),
+        il_instr!("RRA", AddrMode::Absolute, 6, 3, rra),
+        instr!("BVS", AddrMode::Relative, 2, 2, bvs),
+        instr!("ADC", AddrMode::IndirectY, 5, 2, adc),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("RRA", AddrMode::IndirectY, 8, 2, rra),
+        il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
+        instr!("ADC", AddrMode::ZeroPageX, 4, 2, adc),
+        instr!("ROR", AddrMode::ZeroPageX, 6, 2, ror),
+        il_instr!("RRA", AddrMode::ZeroPageX, 6, 2, rra),
+        instr!("SEI", AddrMode::Implied, 2, 1, sei),
+        instr!("ADC", AddrMode::AbsoluteY, 4, 3, adc),
+        il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
+        il_instr!("RRA", AddrMode::AbsoluteY, 7, 3, rra),
+        il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
+        instr!("ADC", AddrMode::AbsoluteX, 4, 3, adc),
+        instr!("ROR", AddrMode::AbsoluteX, 7, 3, ror),
+        il_instr!("RRA", AddrMode::AbsoluteX, 7, 3, rra),
+        il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
+        instr!("STA", AddrMode::IndirectX, 6, 2, sta),
+        il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
+        il_instr!("SAX", AddrMode::IndirectX, 6, 2, sax),
+        instr!("STY", AddrMode::ZeroPage, 3, 2, sty),
+        instr!("STA", AddrMode::ZeroPage, 3, 2, sta),
+        instr!("STX", AddrMode::ZeroPage, 3, 2, stx),
+        il_instr!("SAX", AddrMode::ZeroPage, 3, 2, sax),
+        instr!("DEY", AddrMode::Implied, 2, 1, dey),
+        il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
+        instr!("TXA", AddrMode::Implied, 2, 1, txa),
+        il_instr!("XAA", AddrMode::Immediate, 2, 2, xaa),
+        instr!("STY", AddrMode::Absolute, 4, 3, sty),
+        instr!("STA", AddrMode::Absolute, 4, 3, sta),
+        instr!("STX", AddrMode::Absolute, 4, 3, stx),
+        il_instr!("SAX", AddrMode::Absolute, 4, 3, sax),
+        instr!("BCC", AddrMode::Relative, 2, 2, bcc),
+        instr!("STA", AddrMode::IndirectY, 6, 2, sta),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("AXA", AddrMode::IndirectY, 6, 2, axa),
+        instr!("STY", AddrMode::ZeroPageX, 4, 2, sty),
+        instr!("STA", AddrMode::ZeroPageX, 4, 2, sta),
+        instr!("STX", AddrMode::ZeroPageY, 4, 2, stx),
+        il_instr!("SAX", AddrMode::ZeroPageY, 4, 2, sax),
+        instr!("TYA", AddrMode::Implied, 2, 1, tya),
+        instr!("STA", AddrMode::AbsoluteY, 5, 3, sta),
+        instr!("TXS", AddrMode::Implied, 2, 1, txs),
+        il_instr!("XAS", AddrMode::AbsoluteY, 5, 3, xas),
+        il_instr!("SYA", AddrMode::AbsoluteX, 5, 3, sya),
+        instr!("STA", AddrMode::AbsoluteX, 5, 3, sta),
+        il_instr!("SXA", AddrMode::AbsoluteY, 5, 3, sxa),
+        il_instr!("AXA", AddrMode::AbsoluteY, 5, 3, axa),
+        instr!("LDY", AddrMode::Immediate, 2, 2, ldy),
+        instr!("LDA", AddrMode::IndirectX, 6, 2, lda),
+        instr!("LDX", AddrMode::Immediate, 2, 2, ldx),
+        il_instr!("LAX", AddrMode::IndirectX, 6, 2, lax),
+        instr!("LDY", AddrMode::ZeroPage, 3, 2, ldy),
+        instr!("LDA", AddrMode::ZeroPage, 3, 2, lda),
+        instr!("LDX", AddrMode::ZeroPage, 3, 2, ldx),
+        il_instr!("LAX", AddrMode::ZeroPage, 3, 2, lax),
+        instr!("TAY", AddrMode::Implied, 2, 1, tay),
+        instr!("LDA", AddrMode::Immediate, 2, 2, lda),
+        instr!("TAX", AddrMode::Implied, 2, 1, tax),
+        il_instr!("LXA", AddrMode::Immediate, 2, 2, lxa),
+        instr!("LDY", AddrMode::Absolute, 4, 3, ldy),
+        instr!("LDA", AddrMode::Absolute, 4, 3, lda),
+        instr!("LDX", AddrMode::Absolute, 4, 3, ldx),
+        il_instr!("LAX", AddrMode::Absolute, 4, 3, lax),
+        instr!("BCS", AddrMode::Relative, 2, 2, bcs),
+        instr!("LDA", AddrMode::IndirectY, 5, 2, lda),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("LAX", AddrMode::IndirectY, 5, 2, lax),
+        instr!("LDY", AddrMode::ZeroPageX, 4, 2, ldy),
+        instr!("LDA", AddrMode::ZeroPageX, 4, 2, lda),
+        instr!("LDX", AddrMode::ZeroPageY, 4, 2, ldx),
+        il_instr!("LAX", AddrMode::ZeroPageY, 4, 2, lax),
+        instr!("CLV", AddrMode::Implied, 2, 1, clv),
+        instr!("LDA", AddrMode::AbsoluteY, 4, 3, lda),
+        instr!("TSX", AddrMode::Implied, 2, 1, tsx),
+        il_instr!("LAS", AddrMode::AbsoluteY, 4, 3, las),
+        instr!("LDY", AddrMode::AbsoluteX, 4, 3, ldy),
+        instr!("LDA", AddrMode::AbsoluteX, 4, 3, lda),
+        instr!("LDX", AddrMode::AbsoluteY, 4, 3, ldx),
+        il_instr!("LAX", AddrMode::AbsoluteY, 4, 3, lax),
+        instr!("CPY", AddrMode::Immediate, 2, 2, cpy),
+        instr!("CMP", AddrMode::IndirectX, 6, 2, cmp),
+        il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
+        il_instr!("DCP", AddrMode::IndirectX, 8, 2, dcp),
+        instr!("CPY", AddrMode::ZeroPage, 3, 2, cpy),
+        instr!("CMP", AddrMode::ZeroPage, 3, 2, cmp),
+        instr!("DEC", AddrMode::ZeroPage, 5, 2, dec),
+        il_instr!("DCP", AddrMode::ZeroPage, 5, 2, dcp),
+        instr!("INY", AddrMode::Implied, 2, 1, iny),
+        instr!("CMP", AddrMode::Immediate, 2, 2, cmp),
+        instr!("DEX", AddrMode::Implied, 2, 1, dex),
+        il_instr!("AXS", AddrMode::Immediate, 2, 2, axs),
+        instr!("CPY", AddrMode::Absolute, 4, 3, cpy),
+        instr!("CMP", AddrMode::Absolute, 4, 3, cmp),
+        instr!("DEC", AddrMode::Absolute, 6, 3, dec),
+        il_instr!("DCP", AddrMode::Absolute, 6, 3, dcp),
+        instr!("BNE", AddrMode::Relative, 2, 2, bne),
+        instr!("CMP", AddrMode::IndirectY, 5, 2, cmp),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("DCP", AddrMode::IndirectY, 8, 2, dcp),
+        il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
+        instr!("CMP", AddrMode::ZeroPageX, 4, 2, cmp),
+        instr!("DEC", AddrMode::ZeroPageX, 6, 2, dec),
+        il_instr!("DCP", AddrMode::ZeroPageX, 6, 2, dcp),
+        instr!("CLD", AddrMode::Implied, 2, 1, cld),
+        instr!("CMP", AddrMode::AbsoluteY, 4, 3, cmp),
+        il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
+        il_instr!("DCP", AddrMode::AbsoluteY, 7, 3, dcp),
+        il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
+        instr!("CMP", AddrMode::AbsoluteX, 4, 3, cmp),
+        instr!("DEC", AddrMode::AbsoluteX, 7, 3, dec),
+        il_instr!("DCP", AddrMode::AbsoluteX, 7, 3, dcp),
+        instr!("CPX", AddrMode::Immediate, 2, 2, cpx),
+        instr!("SBC", AddrMode::IndirectX, 6, 2, sbc),
+        il_instr!("DOP", AddrMode::Immediate, 2, 2, dop),
+        il_instr!("ISB", AddrMode::IndirectX, 8, 2, isb),
+        instr!("CPX", AddrMode::ZeroPage, 3, 2, cpx),
+        instr!("SBC", AddrMode::ZeroPage, 3, 2, sbc),
+        instr!("INC", AddrMode::ZeroPage, 5, 2, inc),
+        il_instr!("ISB", AddrMode::ZeroPage, 5, 2, isb),
+        instr!("INX", AddrMode::Implied, 2, 1, inx),
+        instr!("SBC", AddrMode::Immediate, 2, 2, sbc),
+        instr!("NOP", AddrMode::Implied, 2, 1, nop),
+        il_instr!("SBC", AddrMode::Immediate, 2, 2, sbc),
+        instr!("CPX", AddrMode::Absolute, 4, 3, cpx),
+        instr!("SBC", AddrMode::Absolute, 4, 3, sbc),
+        instr!("INC", AddrMode::Absolute, 6, 3, inc),
+        il_instr!("ISB", AddrMode::Absolute, 6, 3, isb),
+        instr!("BEQ", AddrMode::Relative, 2, 2, beq),
+        instr!("SBC", AddrMode::IndirectY, 5, 2, sbc),
+        il_instr!("KIL", AddrMode::Implied, 2, 1, kil),
+        il_instr!("ISB", AddrMode::IndirectY, 8, 2, isb),
+        il_instr!("DOP", AddrMode::ZeroPageX, 4, 2, dop),
+        instr!("SBC", AddrMode::ZeroPageX, 4, 2, sbc),
+        instr!("INC", AddrMode::ZeroPageX, 6, 2, inc),
+        il_instr!("ISB", AddrMode::ZeroPageX, 6, 2, isb),
+        instr!("SED", AddrMode::Implied, 2, 1, sed),
+        instr!("SBC", AddrMode::AbsoluteY, 4, 3, sbc),
+        il_instr!("NOP", AddrMode::Implied, 2, 1, nop),
+        il_instr!("ISB", AddrMode::AbsoluteY, 7, 3, isb),
+        il_instr!("TOP", AddrMode::AbsoluteX, 4, 3, top),
+        instr!("SBC", AddrMode::AbsoluteX, 4, 3, sbc),
+        instr!("INC", AddrMode::AbsoluteX, 7, 3, inc),
+        il_instr!("ISB", AddrMode::AbsoluteX, 7, 3, isb),
+    ];
+}
+
+// The parts of each instruction that don't depend on the bus, for tools that
+// only need to know what an opcode is
+#[derive(Clone, Copy)]
+pub struct Opcode {
     pub name: &'static str,
     pub mode: AddrMode,
-    pub cycles: usize,
     pub len: u8,
-    pub operate: fn(&mut NES, mode: &AddrMode) -> usize,
     pub legal: bool,
 }
 
-impl Instruction {
-    pub fn execute(&self, nes: &mut NES) -> usize {
-        (self.operate)(nes, &self.mode) + self.cycles
+// Every bus gets the same names, modes and lengths, so any table will do
+pub const OPCODES: [Opcode; 256] = opcodes(&Instructions::<FlatRam>::TABLE);
+
+const fn opcodes<B>(table: &[Instruction<B>; 256]) -> [Opcode; 256] {
+    let mut opcodes = [Opcode {
+        name: "",
+        mode: AddrMode::Implied,
+        len: 0,
+        legal: false,
+    }; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let instruction = &table[i];
+
+        opcodes[i] = Opcode {
+            name: instruction.name,
+            mode: instruction.mode,
+            len: instruction.len,
+            legal: instruction.legal,
+        };
+        i += 1;
     }
 
-    pub fn size(&self) -> u16 {
-        (self.len - 1).into()
-    }
+    opcodes
 }
 
-fn adc(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-
-    add_to_accumulator(nes, value);
+pub struct Instruction<B> {
+    pub name: &'static str,
+    pub mode: AddrMode,
+    // Base cycle count from the opcode tables, the emulated timing comes from
+    // the bus accesses each instruction makes
+    #[allow(dead_code)]
+    pub cycles: usize,
+    pub len: u8,
+    pub operate: fn(&mut Cpu, &mut B, mode: &AddrMode),
+    pub legal: bool,
+}
 
-    page_crossed.into()
+impl<B: Bus> Instruction<B> {
+    pub fn execute(&self, cpu: &mut Cpu, bus: &mut B) {
+        (self.operate)(cpu, bus, &self.mode)
+    }
 }
 
-fn and(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
+fn adc<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
 
-    set_accumulator(nes, nes.cpu_registers.accumulator & value);
+    if decimal_enabled(cpu) {
+        add_decimal(cpu, value);
+    } else {
+        add_to_accumulator(cpu, value);
+    }
+}
+
+fn and<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
 
-    page_crossed.into()
+    set_accumulator(cpu, cpu.registers.accumulator & value);
 }
 
-fn asl(nes: &mut NES, mode: &AddrMode) -> usize {
+fn asl<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
     let old_value = if let AddrMode::Accumulator = mode {
-        let old_value = nes.cpu_registers.accumulator;
+        let old_value = cpu.registers.accumulator;
 
-        set_accumulator(nes, old_value << 1);
+        set_accumulator(cpu, old_value << 1);
 
         old_value
     } else {
-        let (addr, _) = nes.get_operating_address(mode);
-        let old_value = nes.cpu_read(addr);
+        let addr = write_address(cpu, bus, mode);
+        let old_value = read_for_modify(cpu, bus, addr);
         let result = old_value << 1;
 
-        nes.cpu_write(addr, result);
-        update_zero_and_negative_flags(nes, result);
+        cpu.write(bus, addr, result);
+        update_zero_and_negative_flags(cpu, result);
 
         old_value
     };
 
-    nes.cpu_registers.status.set_carry(old_value >> 7 == 1);
-
-    0
+    cpu.registers.status.set_carry(old_value >> 7 == 1);
 }
 
-fn bcc(nes: &mut NES, _mode: &AddrMode) -> usize {
-    branch(nes, !nes.cpu_registers.status.carry())
+fn bcc<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    branch(cpu, bus, mode, !cpu.registers.status.carry())
 }
 
-fn bcs(nes: &mut NES, _mode: &AddrMode) -> usize {
-    branch(nes, nes.cpu_registers.status.carry())
+fn bcs<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    branch(cpu, bus, mode, cpu.registers.status.carry())
 }
 
-fn beq(nes: &mut NES, _mode: &AddrMode) -> usize {
-    branch(nes, nes.cpu_registers.status.zero())
+fn beq<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    branch(cpu, bus, mode, cpu.registers.status.zero())
 }
 
-fn bit(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = value & nes.cpu_registers.accumulator;
+fn bit<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
+    let result = value & cpu.registers.accumulator;
 
-    nes.cpu_registers.status.set_zero(result == 0);
-    nes.cpu_registers.status.set_overflow(value & 0x40 > 0);
-    nes.cpu_registers.status.set_negative(value >> 7 == 1);
-
-    0
+    cpu.registers.status.set_zero(result == 0);
+    cpu.registers.status.set_overflow(value & 0x40 > 0);
+    cpu.registers.status.set_negative(value >> 7 == 1);
 }
 
-fn bmi(nes: &mut NES, _mode: &AddrMode) -> usize {
-    branch(nes, nes.cpu_registers.status.negative())
+fn bmi<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    branch(cpu, bus, mode, cpu.registers.status.negative())
 }
 
-fn bne(nes: &mut NES, _mode: &AddrMode) -> usize {
-    branch(nes, !nes.cpu_registers.status.zero())
+fn bne<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    branch(cpu, bus, mode, !cpu.registers.status.zero())
 }
 
-fn bpl(nes: &mut NES, _mode: &AddrMode) -> usize {
-    branch(nes, !nes.cpu_registers.status.negative())
+fn bpl<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    branch(cpu, bus, mode, !cpu.registers.status.negative())
 }
 
-fn brk(_nes: &mut NES, _mode: &AddrMode) -> usize {
-    // TODO: interrupts
-    // std::process::exit(0);
-    0
-}
+// The byte after BRK is padding, it was already read and is skipped over
+fn brk<B: Bus>(cpu: &mut Cpu, bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.program_counter = cpu.registers.program_counter.wrapping_add(1);
 
-fn bvc(nes: &mut NES, _mode: &AddrMode) -> usize {
-    branch(nes, !nes.cpu_registers.status.overflow())
+    cpu.interrupt_sequence(bus, true);
 }
 
-fn bvs(nes: &mut NES, _mode: &AddrMode) -> usize {
-    branch(nes, nes.cpu_registers.status.overflow())
+fn bvc<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    branch(cpu, bus, mode, !cpu.registers.status.overflow())
 }
 
-fn clc(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.status.set_carry(false);
-
-    0
+fn bvs<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    branch(cpu, bus, mode, cpu.registers.status.overflow())
 }
 
-fn cld(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.status.set_decimal(false);
-
-    0
+fn clc<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.status.set_carry(false);
 }
 
-fn cli(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.status.set_interrupt_disable(false);
-
-    0
+fn cld<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.status.set_decimal(false);
 }
 
-fn clv(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.status.set_overflow(false);
-
-    0
+fn cli<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.status.set_interrupt_disable(false);
 }
 
-fn cmp(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
+fn clv<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.status.set_overflow(false);
+}
 
-    compare(nes, nes.cpu_registers.accumulator, value);
+fn cmp<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
 
-    page_crossed.into()
+    compare(cpu, cpu.registers.accumulator, value);
 }
 
-fn cpx(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-
-    compare(nes, nes.cpu_registers.x, value);
+fn cpx<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
 
-    0
+    compare(cpu, cpu.registers.x, value);
 }
 
-fn cpy(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
+fn cpy<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
 
-    compare(nes, nes.cpu_registers.y, value);
-
-    0
+    compare(cpu, cpu.registers.y, value);
 }
 
-fn dec(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let old_value = nes.cpu_read(addr);
+fn dec<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let old_value = read_for_modify(cpu, bus, addr);
     let result = old_value.wrapping_sub(1);
 
-    nes.cpu_write(addr, result);
-    update_zero_and_negative_flags(nes, result);
-
-    0
+    cpu.write(bus, addr, result);
+    update_zero_and_negative_flags(cpu, result);
 }
 
-fn dex(nes: &mut NES, _mode: &AddrMode) -> usize {
-    let result = nes.cpu_registers.x.wrapping_sub(1);
-
-    nes.cpu_registers.x = result;
-    update_zero_and_negative_flags(nes, result);
+fn dex<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    let result = cpu.registers.x.wrapping_sub(1);
 
-    0
+    cpu.registers.x = result;
+    update_zero_and_negative_flags(cpu, result);
 }
 
-fn dey(nes: &mut NES, _mode: &AddrMode) -> usize {
-    let result = nes.cpu_registers.y.wrapping_sub(1);
+fn dey<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    let result = cpu.registers.y.wrapping_sub(1);
 
-    nes.cpu_registers.y = result;
-    update_zero_and_negative_flags(nes, result);
-
-    0
+    cpu.registers.y = result;
+    update_zero_and_negative_flags(cpu, result);
 }
 
-fn eor(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = nes.cpu_registers.accumulator ^ value;
-
-    set_accumulator(nes, result);
+fn eor<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
+    let result = cpu.registers.accumulator ^ value;
 
-    page_crossed.into()
+    set_accumulator(cpu, result);
 }
 
-fn inc(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-
-    increment_memory(nes, addr);
+fn inc<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
 
-    0
+    increment_memory(cpu, bus, addr);
 }
 
-fn inx(nes: &mut NES, _mode: &AddrMode) -> usize {
-    let result = nes.cpu_registers.x.wrapping_add(1);
+fn inx<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    let result = cpu.registers.x.wrapping_add(1);
 
-    nes.cpu_registers.x = result;
-    update_zero_and_negative_flags(nes, result);
-
-    0
+    cpu.registers.x = result;
+    update_zero_and_negative_flags(cpu, result);
 }
 
-fn iny(nes: &mut NES, _mode: &AddrMode) -> usize {
-    let result = nes.cpu_registers.y.wrapping_add(1);
-
-    nes.cpu_registers.y = result;
-    update_zero_and_negative_flags(nes, result);
+fn iny<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    let result = cpu.registers.y.wrapping_add(1);
 
-    0
+    cpu.registers.y = result;
+    update_zero_and_negative_flags(cpu, result);
 }
 
-fn jmp(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-
-    nes.cpu_registers.program_counter = addr;
+fn jmp<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let (addr, _) = cpu.operating_address(bus, mode);
 
-    0
+    cpu.registers.program_counter = addr;
 }
 
-fn jsr(nes: &mut NES, mode: &AddrMode) -> usize {
-    nes.stack_push_u16(nes.cpu_registers.program_counter + 2 - 1);
+fn jsr<B: Bus>(cpu: &mut Cpu, bus: &mut B, _mode: &AddrMode) {
+    let low = cpu.fetch(bus);
 
-    let (addr, _) = nes.get_operating_address(mode);
+    read_stack(cpu, bus);
+    cpu.stack_push_u16(bus, cpu.registers.program_counter);
 
-    nes.cpu_registers.program_counter = addr;
+    let high = cpu.read(bus, cpu.registers.program_counter);
 
-    0
+    cpu.registers.program_counter = u16::from_le_bytes([low, high]);
 }
 
-fn lda(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-    let set = nes.cpu_read(addr);
+fn lda<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let set = read_operand(cpu, bus, mode);
 
-    set_accumulator(nes, set);
-
-    page_crossed.into()
+    set_accumulator(cpu, set);
 }
 
-fn ldx(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-
-    nes.cpu_registers.x = nes.cpu_read(addr);
-    update_zero_and_negative_flags(nes, nes.cpu_registers.x);
-
-    page_crossed.into()
+fn ldx<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    cpu.registers.x = read_operand(cpu, bus, mode);
+    update_zero_and_negative_flags(cpu, cpu.registers.x);
 }
 
-fn ldy(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-
-    nes.cpu_registers.y = nes.cpu_read(addr);
-    update_zero_and_negative_flags(nes, nes.cpu_registers.y);
-
-    page_crossed.into()
+fn ldy<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    cpu.registers.y = read_operand(cpu, bus, mode);
+    update_zero_and_negative_flags(cpu, cpu.registers.y);
 }
 
-fn lsr(nes: &mut NES, mode: &AddrMode) -> usize {
+fn lsr<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
     let old_value = match mode {
         AddrMode::Accumulator => {
-            let old_value = nes.cpu_registers.accumulator;
+            let old_value = cpu.registers.accumulator;
 
-            set_accumulator(nes, old_value >> 1);
+            set_accumulator(cpu, old_value >> 1);
 
             old_value
         }
         _ => {
-            let (addr, _) = nes.get_operating_address(mode);
-            let old_value = nes.cpu_read(addr);
+            let addr = write_address(cpu, bus, mode);
+            let old_value = read_for_modify(cpu, bus, addr);
             let result = old_value >> 1;
 
-            nes.cpu_write(addr, result);
-            update_zero_and_negative_flags(nes, result);
+            cpu.write(bus, addr, result);
+            update_zero_and_negative_flags(cpu, result);
 
             old_value
         }
     };
 
-    nes.cpu_registers.status.set_carry(old_value & 1 == 1);
-
-    0
+    cpu.registers.status.set_carry(old_value & 1 == 1);
 }
 
-fn nop(_nes: &mut NES, _mode: &AddrMode) -> usize {
-    0
-}
-
-fn ora(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = nes.cpu_registers.accumulator | value;
+fn nop<B: Bus>(_cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {}
 
-    set_accumulator(nes, result);
+fn ora<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
+    let result = cpu.registers.accumulator | value;
 
-    page_crossed.into()
+    set_accumulator(cpu, result);
 }
 
-fn pha(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.stack_push(nes.cpu_registers.accumulator);
-
-    0
+fn pha<B: Bus>(cpu: &mut Cpu, bus: &mut B, _mode: &AddrMode) {
+    cpu.stack_push(bus, cpu.registers.accumulator);
 }
 
-fn php(nes: &mut NES, _mode: &AddrMode) -> usize {
-    let mut status = nes.cpu_registers.status.clone();
+fn php<B: Bus>(cpu: &mut Cpu, bus: &mut B, _mode: &AddrMode) {
+    let mut status = cpu.registers.status.clone();
     status.set_b(0b11);
 
-    nes.stack_push(status.bits());
-
-    0
+    cpu.stack_push(bus, status.bits());
 }
 
-fn pla(nes: &mut NES, _mode: &AddrMode) -> usize {
-    let result = nes.stack_pop();
+fn pla<B: Bus>(cpu: &mut Cpu, bus: &mut B, _mode: &AddrMode) {
+    read_stack(cpu, bus);
 
-    set_accumulator(nes, result);
+    let result = cpu.stack_pop(bus);
 
-    0
+    set_accumulator(cpu, result);
 }
 
-fn plp(nes: &mut NES, _mode: &AddrMode) -> usize {
-    let result = nes.stack_pop();
+fn plp<B: Bus>(cpu: &mut Cpu, bus: &mut B, _mode: &AddrMode) {
+    read_stack(cpu, bus);
 
-    nes.cpu_registers.status.set_bits(result);
-    nes.cpu_registers.status.set_b(0b10);
+    let result = cpu.stack_pop(bus);
 
-    0
+    cpu.registers.status.set_bits(result);
+    cpu.registers.status.set_b(0b10);
 }
 
-fn rol(nes: &mut NES, mode: &AddrMode) -> usize {
+fn rol<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
     let old_value = match mode {
         AddrMode::Accumulator => {
-            let old_value = nes.cpu_registers.accumulator;
-            let result = (old_value << 1) | (nes.cpu_registers.status.carry() as u8);
+            let old_value = cpu.registers.accumulator;
+            let result = (old_value << 1) | (cpu.registers.status.carry() as u8);
 
-            set_accumulator(nes, result);
+            set_accumulator(cpu, result);
 
             old_value
         }
         _ => {
-            let (addr, _) = nes.get_operating_address(mode);
-            let old_value = nes.cpu_read(addr);
-            let result = (old_value << 1) | (nes.cpu_registers.status.carry() as u8);
+            let addr = write_address(cpu, bus, mode);
+            let old_value = read_for_modify(cpu, bus, addr);
+            let result = (old_value << 1) | (cpu.registers.status.carry() as u8);
 
-            nes.cpu_write(addr, result);
-            update_zero_and_negative_flags(nes, result);
+            cpu.write(bus, addr, result);
+            update_zero_and_negative_flags(cpu, result);
 
             old_value
         }
     };
 
-    nes.cpu_registers.status.set_carry(old_value >> 7 == 1);
-
-    0
+    cpu.registers.status.set_carry(old_value >> 7 == 1);
 }
 
-fn ror(nes: &mut NES, mode: &AddrMode) -> usize {
+fn ror<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
     let old_value = match mode {
         AddrMode::Accumulator => {
-            let old_value = nes.cpu_registers.accumulator;
-            let result = (old_value >> 1) | ((nes.cpu_registers.status.carry() as u8) << 7);
+            let old_value = cpu.registers.accumulator;
+            let result = (old_value >> 1) | ((cpu.registers.status.carry() as u8) << 7);
 
-            set_accumulator(nes, result);
+            set_accumulator(cpu, result);
 
             old_value
         }
         _ => {
-            let (addr, _) = nes.get_operating_address(mode);
-            let old_value = nes.cpu_read(addr);
-            let result = (old_value >> 1) | ((nes.cpu_registers.status.carry() as u8) << 7);
+            let addr = write_address(cpu, bus, mode);
+            let old_value = read_for_modify(cpu, bus, addr);
+            let result = (old_value >> 1) | ((cpu.registers.status.carry() as u8) << 7);
 
-            nes.cpu_write(addr, result);
-            update_zero_and_negative_flags(nes, result);
+            cpu.write(bus, addr, result);
+            update_zero_and_negative_flags(cpu, result);
 
             old_value
         }
     };
 
-    nes.cpu_registers.status.set_carry(old_value & 1 == 1);
-
-    0
+    cpu.registers.status.set_carry(old_value & 1 == 1);
 }
 
-fn rti(nes: &mut NES, _mode: &AddrMode) -> usize {
-    let status = nes.stack_pop();
-    let program_counter = nes.stack_pop_u16();
+fn rti<B: Bus>(cpu: &mut Cpu, bus: &mut B, _mode: &AddrMode) {
+    read_stack(cpu, bus);
 
-    nes.cpu_registers.status.set_bits(status);
-    nes.cpu_registers.status.set_b(0b10);
+    // The flags change before the return address is pulled, so an IRQ that RTI
+    // enables is taken straight after it
+    let status = cpu.stack_pop(bus);
+    cpu.registers.status.set_bits(status);
+    cpu.registers.status.set_b(0b10);
 
-    nes.cpu_registers.program_counter = program_counter;
-
-    0
+    cpu.registers.program_counter = cpu.stack_pop_u16(bus);
 }
 
-fn rts(nes: &mut NES, _mode: &AddrMode) -> usize {
-    let program_counter = nes.stack_pop_u16() + 1;
+fn rts<B: Bus>(cpu: &mut Cpu, bus: &mut B, _mode: &AddrMode) {
+    read_stack(cpu, bus);
+
+    let program_counter = cpu.stack_pop_u16(bus);
 
-    nes.cpu_registers.program_counter = program_counter;
+    cpu.read(bus, program_counter);
 
-    0
+    cpu.registers.program_counter = program_counter.wrapping_add(1);
 }
 
-fn sbc(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-    let temp = nes.cpu_read(addr);
+fn sbc<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let temp = read_operand(cpu, bus, mode);
     let value = temp.wrapping_neg().wrapping_sub(1);
 
-    add_to_accumulator(nes, value);
+    let accumulator = cpu.registers.accumulator;
+    let carry = cpu.registers.status.carry();
 
-    page_crossed.into()
-}
-
-fn sec(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.status.set_carry(true);
+    add_to_accumulator(cpu, value);
 
-    0
+    // The flags still come from the binary result
+    if decimal_enabled(cpu) {
+        cpu.registers.accumulator = subtract_decimal(accumulator, temp, carry);
+    }
 }
 
-fn sed(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.status.set_decimal(true);
-
-    0
+fn sec<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.status.set_carry(true);
 }
 
-fn sei(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.status.set_interrupt_disable(true);
-
-    0
+fn sed<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.status.set_decimal(true);
 }
 
-fn sta(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_registers.accumulator;
-
-    nes.cpu_write(addr, value);
-
-    0
+fn sei<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.status.set_interrupt_disable(true);
 }
 
-fn stx(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_registers.x;
-
-    nes.cpu_write(addr, value);
+fn sta<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let value = cpu.registers.accumulator;
 
-    0
+    cpu.write(bus, addr, value);
 }
 
-fn sty(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_registers.y;
-
-    nes.cpu_write(addr, value);
+fn stx<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let value = cpu.registers.x;
 
-    0
+    cpu.write(bus, addr, value);
 }
 
-fn tax(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.x = nes.cpu_registers.accumulator;
+fn sty<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let value = cpu.registers.y;
 
-    update_zero_and_negative_flags(nes, nes.cpu_registers.x);
-
-    0
+    cpu.write(bus, addr, value);
 }
 
-fn tay(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.y = nes.cpu_registers.accumulator;
-
-    update_zero_and_negative_flags(nes, nes.cpu_registers.y);
+fn tax<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.x = cpu.registers.accumulator;
 
-    0
+    update_zero_and_negative_flags(cpu, cpu.registers.x);
 }
 
-fn tsx(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.x = nes.cpu_registers.stack_pointer;
-
-    update_zero_and_negative_flags(nes, nes.cpu_registers.x);
+fn tay<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.y = cpu.registers.accumulator;
 
-    0
+    update_zero_and_negative_flags(cpu, cpu.registers.y);
 }
 
-fn txa(nes: &mut NES, _mode: &AddrMode) -> usize {
-    set_accumulator(nes, nes.cpu_registers.x);
+fn tsx<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.x = cpu.registers.stack_pointer;
 
-    0
+    update_zero_and_negative_flags(cpu, cpu.registers.x);
 }
 
-fn txs(nes: &mut NES, _mode: &AddrMode) -> usize {
-    nes.cpu_registers.stack_pointer = nes.cpu_registers.x;
-
-    0
+fn txa<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    set_accumulator(cpu, cpu.registers.x);
 }
 
-fn tya(nes: &mut NES, _mode: &AddrMode) -> usize {
-    set_accumulator(nes, nes.cpu_registers.y);
+fn txs<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    cpu.registers.stack_pointer = cpu.registers.x;
+}
 
-    0
+fn tya<B: Bus>(cpu: &mut Cpu, _bus: &mut B, _mode: &AddrMode) {
+    set_accumulator(cpu, cpu.registers.y);
 }
 
 // ILLEGAL INSTRUCTIONS
 
-fn anc(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
+fn anc<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
 
-    let result = nes.cpu_registers.accumulator & value;
+    let result = cpu.registers.accumulator & value;
 
-    set_accumulator(nes, result);
+    set_accumulator(cpu, result);
 
-    nes.cpu_registers
+    cpu.registers
         .status
-        .set_carry(nes.cpu_registers.status.negative());
-
-    0
+        .set_carry(cpu.registers.status.negative());
 }
 
-fn arr(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
+fn arr<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
 
-    let result = ((nes.cpu_registers.accumulator & value) >> 1)
-        | ((nes.cpu_registers.status.carry() as u8) << 7);
+    let result =
+        ((cpu.registers.accumulator & value) >> 1) | ((cpu.registers.status.carry() as u8) << 7);
 
-    nes.cpu_registers.status.set_carry(value & 1 == 1);
+    cpu.registers.status.set_carry(value & 1 == 1);
 
-    set_accumulator(nes, result);
+    set_accumulator(cpu, result);
 
-    let accumulator = nes.cpu_registers.accumulator;
+    let accumulator = cpu.registers.accumulator;
     let fifth_bit = (accumulator >> 5) & 1;
     let sixth_bit = (accumulator >> 6) & 1;
 
-    nes.cpu_registers.status.set_carry(sixth_bit == 1);
-    nes.cpu_registers
+    cpu.registers.status.set_carry(sixth_bit == 1);
+    cpu.registers
         .status
         .set_overflow(fifth_bit ^ sixth_bit == 1);
-    update_zero_and_negative_flags(nes, accumulator);
-
-    0
+    update_zero_and_negative_flags(cpu, accumulator);
 }
 
-fn asr(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-
-    let result = value >> 1;
+fn asr<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = cpu.registers.accumulator & read_operand(cpu, bus, mode);
 
-    nes.cpu_write(addr, result);
+    cpu.registers.status.set_carry(value & 1 == 1);
 
-    nes.cpu_registers.status.set_carry(value & 1 == 1);
+    set_accumulator(cpu, value >> 1);
+}
 
-    set_accumulator(nes, result & nes.cpu_registers.accumulator);
+fn axa<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = cpu.registers.x & cpu.registers.accumulator;
 
-    0
+    store_and_high_byte(cpu, bus, mode, value);
 }
 
-fn axa(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_registers.x & nes.cpu_registers.accumulator & (addr >> 8) as u8;
+fn axs<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
+    let x_and_a = cpu.registers.x & cpu.registers.accumulator;
 
-    nes.cpu_write(addr, value);
+    compare(cpu, x_and_a, value);
 
-    0
+    cpu.registers.x = x_and_a.wrapping_sub(value);
 }
 
-fn axs(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let x_and_a = nes.cpu_registers.x & nes.cpu_registers.accumulator;
-    let result = x_and_a.wrapping_sub(value);
-
-    if value <= x_and_a {
-        nes.cpu_registers.status.set_carry(true);
-    }
-    update_zero_and_negative_flags(nes, result);
+fn dcp<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let value = read_for_modify(cpu, bus, addr);
+    let result = value.wrapping_sub(1);
 
-    nes.cpu_registers.x = result;
+    cpu.write(bus, addr, result);
 
-    0
+    compare(cpu, cpu.registers.accumulator, result);
 }
 
-fn dcp(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = value.wrapping_sub(1);
+fn dop<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    read_operand(cpu, bus, mode);
+}
 
-    nes.cpu_write(addr, result);
+fn isb<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let value = increment_memory(cpu, bus, addr);
+    let result = (value as i8).wrapping_neg().wrapping_sub(1) as u8;
 
-    if result < nes.cpu_registers.accumulator {
-        nes.cpu_registers.status.set_carry(true);
-    }
+    add_to_accumulator(cpu, result);
+}
 
-    update_zero_and_negative_flags(nes, nes.cpu_registers.accumulator.wrapping_sub(result));
+// Stops the CPU mid-instruction, it never fetches another opcode
+fn kil<B: Bus>(cpu: &mut Cpu, bus: &mut B, _mode: &AddrMode) {
+    let program_counter = cpu.registers.program_counter.wrapping_sub(1);
 
-    0
+    cpu.jam(program_counter, bus.peek(program_counter));
 }
 
-fn dop(_nes: &mut NES, _mode: &AddrMode) -> usize {
-    0
-}
+fn las<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
+    let result = cpu.registers.stack_pointer & value;
 
-fn isb(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = increment_memory(nes, addr);
-    let result = (value as i8).wrapping_neg().wrapping_sub(1) as u8;
+    set_accumulator(cpu, result);
+    cpu.registers.x = result;
+    cpu.registers.stack_pointer = result;
+}
 
-    add_to_accumulator(nes, result);
+fn lax<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
 
-    0
+    cpu.registers.x = value;
+    set_accumulator(cpu, value);
 }
 
-fn kil(_nes: &mut NES, _mode: &AddrMode) -> usize {
-    0
+fn lxa<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
+    let magic = cpu.unstable_opcodes.lxa_magic;
+    let result = (cpu.registers.accumulator | magic) & value;
+
+    cpu.registers.x = result;
+    set_accumulator(cpu, result);
 }
 
-fn las(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = nes.cpu_registers.stack_pointer & value;
+fn rla<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let value = read_for_modify(cpu, bus, addr);
+    let result = (value << 1) | (cpu.registers.status.carry() as u8);
+
+    cpu.write(bus, addr, result);
 
-    set_accumulator(nes, result);
-    nes.cpu_registers.x = result;
-    nes.cpu_registers.stack_pointer = result;
+    cpu.registers.status.set_carry(value >> 7 == 1);
 
-    page_crossed.into()
+    set_accumulator(cpu, cpu.registers.accumulator & result);
 }
 
-fn lax(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, page_crossed) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
+fn rra<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let value = read_for_modify(cpu, bus, addr);
+    let result = (value >> 1) | (cpu.registers.status.carry() as u8) << 7;
 
-    nes.cpu_registers.x = value;
-    set_accumulator(nes, value);
+    cpu.write(bus, addr, result);
 
-    page_crossed.into()
+    cpu.registers.status.set_carry(value & 1 == 1);
+
+    add_to_accumulator(cpu, result);
 }
 
-fn lxa(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = nes.cpu_registers.accumulator & value;
+fn sax<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
 
-    nes.cpu_registers.x = result;
-    set_accumulator(nes, result);
+    let result = cpu.registers.accumulator & cpu.registers.x;
 
-    0
+    cpu.write(bus, addr, result);
 }
 
-fn rla(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = (value << 1) | (nes.cpu_registers.status.carry() as u8);
-
-    nes.cpu_write(addr, result);
+fn slo<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let value = read_for_modify(cpu, bus, addr);
+    let result = value << 1;
 
-    nes.cpu_registers.status.set_carry(value >> 7 == 1);
+    cpu.write(bus, addr, result);
 
-    set_accumulator(nes, nes.cpu_registers.accumulator & result);
+    cpu.registers.status.set_carry(value >> 7 == 1);
 
-    0
+    set_accumulator(cpu, result | cpu.registers.accumulator);
 }
 
-fn rra(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = (value >> 1) | (nes.cpu_registers.status.carry() as u8) << 7;
+fn sre<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let addr = write_address(cpu, bus, mode);
+    let value = read_for_modify(cpu, bus, addr);
+    let result = value >> 1;
 
-    nes.cpu_write(addr, result);
+    cpu.write(bus, addr, result);
 
-    nes.cpu_registers.status.set_carry(value & 1 == 1);
+    cpu.registers.status.set_carry(value & 1 == 1);
 
-    add_to_accumulator(nes, result);
+    set_accumulator(cpu, result ^ cpu.registers.accumulator);
+}
 
-    0
+fn sxa<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    store_and_high_byte(cpu, bus, mode, cpu.registers.x);
 }
 
-fn sax(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
+fn sya<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    store_and_high_byte(cpu, bus, mode, cpu.registers.y);
+}
 
-    let result = nes.cpu_registers.accumulator & nes.cpu_registers.x;
+fn top<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    read_operand(cpu, bus, mode);
+}
 
-    nes.cpu_write(addr, result);
+fn xaa<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let value = read_operand(cpu, bus, mode);
+    let magic = cpu.unstable_opcodes.ane_magic;
 
-    0
+    set_accumulator(
+        cpu,
+        (cpu.registers.accumulator | magic) & cpu.registers.x & value,
+    );
 }
 
-fn slo(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = value << 1;
+fn xas<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) {
+    let result = cpu.registers.x & cpu.registers.accumulator;
+    cpu.registers.stack_pointer = result;
 
-    nes.cpu_write(addr, result);
+    store_and_high_byte(cpu, bus, mode, result);
+}
 
-    nes.cpu_registers.status.set_carry(value >> 7 == 1);
+// Reads the operand of an instruction that only reads memory. Indexed modes
+// first read from the unfixed address when a page boundary is crossed.
+fn read_operand<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) -> u8 {
+    let (addr, page_crossed) = cpu.operating_address(bus, mode);
 
-    set_accumulator(nes, result | nes.cpu_registers.accumulator);
+    if page_crossed {
+        cpu.read(bus, addr.wrapping_sub(0x100));
+    }
 
-    0
+    cpu.read(bus, addr)
 }
 
-fn sre(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
-    let result = value >> 1;
+// Instructions that write always spend the fix-up cycle of indexed modes
+fn write_address<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode) -> u16 {
+    let (addr, page_crossed) = cpu.operating_address(bus, mode);
 
-    nes.cpu_write(addr, result);
+    if let AddrMode::AbsoluteX | AddrMode::AbsoluteY | AddrMode::IndirectY = mode {
+        let unfixed_addr = if page_crossed {
+            addr.wrapping_sub(0x100)
+        } else {
+            addr
+        };
 
-    nes.cpu_registers.status.set_carry(value & 1 == 1);
-
-    set_accumulator(nes, result ^ nes.cpu_registers.accumulator);
+        cpu.read(bus, unfixed_addr);
+    }
 
-    0
+    addr
 }
 
-fn sxa(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_registers.x & ((addr >> 8) as u8 + 1);
+// The SH* stores AND the value with the high byte of the base address plus one.
+// When indexing crosses a page the high byte of the address gets mixed up with
+// the value as well, so the write lands wherever the result points. Either can
+// be turned off in the CPU's unstable opcode settings.
+fn store_and_high_byte<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode, value: u8) {
+    let (addr, page_crossed) = cpu.operating_address(bus, mode);
+    let unfixed_addr = if page_crossed {
+        addr.wrapping_sub(0x100)
+    } else {
+        addr
+    };
+
+    cpu.read(bus, unfixed_addr);
 
-    nes.cpu_write(addr, value);
+    let unstable = cpu.unstable_opcodes;
+    let value = if unstable.and_high_byte {
+        value & ((unfixed_addr >> 8) as u8).wrapping_add(1)
+    } else {
+        value
+    };
+    let addr = if page_crossed && unstable.page_cross_address {
+        ((value as u16) << 8) | (addr & 0x00FF)
+    } else {
+        addr
+    };
 
-    0
+    cpu.write(bus, addr, value);
 }
 
-fn sya(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_registers.y & ((addr >> 8) as u8 + 1);
+// Read-modify-write instructions write the unmodified value back while they
+// work out the result
+fn read_for_modify<B: Bus>(cpu: &mut Cpu, bus: &mut B, addr: u16) -> u8 {
+    let value = cpu.read(bus, addr);
 
-    nes.cpu_write(addr, value);
+    cpu.write(bus, addr, value);
 
-    0
+    value
 }
 
-fn top(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (_, page_crossed) = nes.get_operating_address(mode);
-
-    page_crossed.into()
+fn read_stack<B: Bus>(cpu: &mut Cpu, bus: &mut B) {
+    cpu.read(bus, 0x0100 + cpu.registers.stack_pointer as u16);
 }
 
-// This guy isn't super well documented, this seems like what it does?
-fn xaa(nes: &mut NES, mode: &AddrMode) -> usize {
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = nes.cpu_read(addr);
+fn add_to_accumulator(cpu: &mut Cpu, value: u8) {
+    let result: u16 = cpu.registers.accumulator as u16
+        + value as u16
+        + Into::<u16>::into(cpu.registers.status.carry());
+    cpu.registers.status.set_carry(result > 0xFF);
+
+    let result = result as u8;
 
-    set_accumulator(nes, nes.cpu_registers.x);
-    set_accumulator(nes, nes.cpu_registers.accumulator & value);
+    cpu.registers
+        .status
+        .set_overflow((value ^ result) & (result ^ cpu.registers.accumulator) & 0x80 != 0);
 
-    0
+    set_accumulator(cpu, result);
 }
 
-fn xas(nes: &mut NES, mode: &AddrMode) -> usize {
-    let result = nes.cpu_registers.x & nes.cpu_registers.accumulator;
-    nes.cpu_registers.stack_pointer = result;
+fn decimal_enabled(cpu: &Cpu) -> bool {
+    cpu.decimal_mode && cpu.registers.status.decimal()
+}
+
+// NMOS BCD addition, Z comes from the binary sum while N and V are taken before
+// the high digit is adjusted
+fn add_decimal(cpu: &mut Cpu, value: u8) {
+    let accumulator = cpu.registers.accumulator as u16;
+    let value = value as u16;
+    let carry = cpu.registers.status.carry() as u16;
+
+    let mut low = (accumulator & 0x0F) + (value & 0x0F) + carry;
+    if low > 0x09 {
+        low += 0x06;
+    }
+
+    let mut high = (accumulator >> 4) + (value >> 4) + (low > 0x0F) as u16;
+    let partial = (high << 4) | (low & 0x0F);
 
-    let (addr, _) = nes.get_operating_address(mode);
-    let value = result & ((addr >> 8) as u8 + 1);
+    let status = &mut cpu.registers.status;
+    status.set_zero((accumulator + value + carry) & 0xFF == 0);
+    status.set_negative(partial & 0x80 != 0);
+    status.set_overflow((accumulator ^ partial) & !(accumulator ^ value) & 0x80 != 0);
 
-    nes.cpu_write(addr, value);
+    if high > 0x09 {
+        high += 0x06;
+    }
 
-    0
+    status.set_carry(high > 0x0F);
+    cpu.registers.accumulator = ((high << 4) | (low & 0x0F)) as u8;
 }
 
-fn add_to_accumulator(nes: &mut NES, value: u8) {
-    let result: u16 = nes.cpu_registers.accumulator as u16
-        + value as u16
-        + Into::<u16>::into(nes.cpu_registers.status.carry());
-    nes.cpu_registers.status.set_carry(result > 0xFF);
+fn subtract_decimal(accumulator: u8, value: u8, carry: bool) -> u8 {
+    let mut low = (accumulator & 0x0F) as i16 - (value & 0x0F) as i16 - !carry as i16;
+    let mut high = (accumulator >> 4) as i16 - (value >> 4) as i16;
 
-    let result = result as u8;
+    if low < 0 {
+        low -= 0x06;
+        high -= 1;
+    }
 
-    nes.cpu_registers
-        .status
-        .set_overflow((value ^ result) & (result ^ nes.cpu_registers.accumulator) & 0x80 != 0);
+    if high < 0 {
+        high -= 0x06;
+    }
 
-    set_accumulator(nes, result);
+    ((high << 4) | (low & 0x0F)) as u8
 }
 
-fn increment_memory(nes: &mut NES, addr: u16) -> u8 {
-    let old_value = nes.cpu_read(addr);
+fn increment_memory<B: Bus>(cpu: &mut Cpu, bus: &mut B, addr: u16) -> u8 {
+    let old_value = read_for_modify(cpu, bus, addr);
     let result = old_value.wrapping_add(1);
 
-    nes.cpu_write(addr, result);
-    update_zero_and_negative_flags(nes, result);
+    cpu.write(bus, addr, result);
+    update_zero_and_negative_flags(cpu, result);
 
     result
 }
 
-fn set_accumulator(nes: &mut NES, value: u8) {
-    nes.cpu_registers.accumulator = value;
+fn set_accumulator(cpu: &mut Cpu, value: u8) {
+    cpu.registers.accumulator = value;
 
-    update_zero_and_negative_flags(nes, value);
+    update_zero_and_negative_flags(cpu, value);
 }
 
-fn update_zero_and_negative_flags(nes: &mut NES, value: u8) {
-    nes.cpu_registers.status.set_zero(value == 0);
-    nes.cpu_registers.status.set_negative(value >> 7 == 1);
+fn update_zero_and_negative_flags(cpu: &mut Cpu, value: u8) {
+    cpu.registers.status.set_zero(value == 0);
+    cpu.registers.status.set_negative(value >> 7 == 1);
 }
 
-fn branch(nes: &mut NES, condition: bool) -> usize {
+// A taken branch spends a cycle reading the next opcode, and another reading
+// from the wrong page if the target is on a different one
+fn branch<B: Bus>(cpu: &mut Cpu, bus: &mut B, mode: &AddrMode, condition: bool) {
+    let (new_addr, page_crossed) = cpu.operating_address(bus, mode);
+
     if !condition {
-        return 0;
+        return;
     }
 
-    let old_addr = nes.cpu_registers.program_counter.wrapping_add(1);
-
-    let offset = nes.cpu_read(nes.cpu_registers.program_counter) as i8;
-    let new_addr = old_addr.wrapping_add(offset as u16);
+    let old_addr = cpu.registers.program_counter;
 
-    // TODO: For some rason this fucks stuff up? Docs say this is how it should work tho
-    let cycles = if old_addr & 0xFF00 != new_addr & 0xFF00 {
-        2
-    } else {
-        1
-    };
+    cpu.read(bus, old_addr);
 
-    nes.cpu_registers.program_counter = new_addr;
+    if page_crossed {
+        cpu.read(bus, (old_addr & 0xFF00) | (new_addr & 0x00FF));
+    }
 
-    cycles
+    cpu.registers.program_counter = new_addr;
 }
 
-fn compare(nes: &mut NES, register: u8, value: u8) {
+fn compare(cpu: &mut Cpu, register: u8, value: u8) {
     let result = register.wrapping_sub(value);
 
-    nes.cpu_registers.status.set_carry(register >= value);
-    update_zero_and_negative_flags(nes, result);
+    cpu.registers.status.set_carry(register >= value);
+    update_zero_and_negative_flags(cpu, result);
 }
 
 mod test {
     #[allow(unused_imports)]
     use super::*;
+    #[allow(unused_imports)]
+    use crate::{
+        cpu::{flat_ram::FlatRam, UnstableOpcodes},
+        NES,
+    };
+    #[allow(unused_imports)]
+    use alloc::{vec, vec::Vec};
 
     #[test]
     fn lda_sets_accumulator() {
         let mut nes = NES::default();
 
-        nes.cpu_registers.accumulator = 0x00;
-        nes.cpu_registers.program_counter += 1;
+        nes.cpu.registers.accumulator = 0x00;
+        nes.cpu.registers.program_counter += 1;
         nes.cpu_write(0x0001, 0x01);
 
-        lda(&mut nes, &AddrMode::Immediate);
+        nes.with_cpu(|cpu, nes| lda(cpu, nes, &AddrMode::Immediate));
 
-        assert_eq!(nes.cpu_registers.accumulator, 0x01);
+        assert_eq!(nes.cpu.registers.accumulator, 0x01);
     }
 
     #[test]
     fn lda_sets_zero_flag() {
         let mut nes = NES::default();
 
-        nes.cpu_registers.accumulator = 0x00;
-        nes.cpu_registers.program_counter += 1;
+        nes.cpu.registers.accumulator = 0x00;
+        nes.cpu.registers.program_counter += 1;
         nes.cpu_write(0x0001, 0x00);
 
-        lda(&mut nes, &AddrMode::Immediate);
+        nes.with_cpu(|cpu, nes| lda(cpu, nes, &AddrMode::Immediate));
 
-        assert_eq!(nes.cpu_registers.accumulator, 0x00);
-        assert_eq!(nes.cpu_registers.status.zero(), true);
+        assert_eq!(nes.cpu.registers.accumulator, 0x00);
+        assert_eq!(nes.cpu.registers.status.zero(), true);
     }
 
     #[test]
     fn lda_sets_negative_flag() {
         let mut nes = NES::default();
 
-        nes.cpu_registers.accumulator = 0x00;
-        nes.cpu_registers.program_counter += 1;
+        nes.cpu.registers.accumulator = 0x00;
+        nes.cpu.registers.program_counter += 1;
         nes.cpu_write(0x0001, 0xFF);
 
-        lda(&mut nes, &AddrMode::Immediate);
+        nes.with_cpu(|cpu, nes| lda(cpu, nes, &AddrMode::Immediate));
+
+        assert_eq!(nes.cpu.registers.accumulator, 0xFF);
+        assert_eq!(nes.cpu.registers.status.negative(), true);
+    }
+
+    #[allow(dead_code)]
+    fn load(program: Vec<u8>) -> NES {
+        let mut nes = NES::default();
+
+        nes.insert_cart(crate::cartridge::test::test_rom(Some(program)));
+        nes.reset();
+
+        nes
+    }
+
+    #[test]
+    fn reset_takes_seven_cycles() {
+        let nes = load(vec![0xEA]);
+
+        assert_eq!(nes.clock_count, 7);
+        assert_eq!(nes.cpu.registers.program_counter, 0x8000);
+    }
+
+    #[test]
+    fn indexed_reads_spend_a_cycle_crossing_pages() {
+        // LDA $0010,X ; LDA $00F0,X
+        let mut nes = load(vec![0xBD, 0x10, 0x00, 0xBD, 0xF0, 0x00]);
+        nes.cpu.registers.x = 0x20;
+
+        assert_eq!(nes.cpu_clock(), 4);
+        assert_eq!(nes.cpu_clock(), 5);
+    }
 
-        assert_eq!(nes.cpu_registers.accumulator, 0xFF);
-        assert_eq!(nes.cpu_registers.status.negative(), true);
+    #[test]
+    fn indexed_writes_always_spend_the_fix_up_cycle() {
+        // STA $0010,X ; INC $0010,X
+        let mut nes = load(vec![0x9D, 0x10, 0x00, 0xFE, 0x10, 0x00]);
+        nes.cpu.registers.x = 0x01;
+
+        assert_eq!(nes.cpu_clock(), 5);
+        assert_eq!(nes.cpu_clock(), 7);
+    }
+
+    #[test]
+    fn branches_spend_cycles_when_taken_and_crossing_pages() {
+        // BNE +0 ; BEQ +0 ; BNE -128
+        let mut nes = load(vec![0xD0, 0x00, 0xF0, 0x00, 0xD0, 0x80]);
+        nes.cpu.registers.status.set_zero(false);
+
+        assert_eq!(nes.cpu_clock(), 3);
+        assert_eq!(nes.cpu_clock(), 2);
+        assert_eq!(nes.cpu_clock(), 4);
+        assert_eq!(nes.cpu.registers.program_counter, 0x7F86);
+    }
+
+    #[test]
+    fn subroutines_take_six_cycles_each_way() {
+        // JSR $8004 ; NOP ; RTS
+        let mut nes = load(vec![0x20, 0x04, 0x80, 0xEA, 0x60]);
+
+        assert_eq!(nes.cpu_clock(), 6);
+        assert_eq!(nes.cpu.registers.program_counter, 0x8004);
+        assert_eq!(nes.cpu_clock(), 6);
+        assert_eq!(nes.cpu.registers.program_counter, 0x8003);
+    }
+
+    #[test]
+    fn page_crossing_dummy_read_hits_the_bus() {
+        // LDA $20F2,X reads $2007 from the unfixed address before $2107
+        let mut nes = load(vec![0xBD, 0xF2, 0x20]);
+        nes.cpu.registers.x = 0x15;
+
+        nes.cpu_clock();
+
+        assert_eq!(nes.ppu_registers.address.as_u16(), 2);
+    }
+
+    #[test]
+    fn read_modify_write_writes_twice() {
+        // INC $2006 writes the old and new value, setting both address bytes
+        let mut nes = load(vec![0xEE, 0x06, 0x20]);
+        nes.ppu_warmed_up = true;
+        nes.ppu_open_bus = 0x21;
+
+        assert_eq!(nes.cpu_clock(), 6);
+        assert_eq!(nes.ppu_registers.address.as_u16(), 0x2122);
+    }
+
+    #[allow(dead_code)]
+    fn decimal_cpu(accumulator: u8, carry: bool) -> Cpu {
+        let mut cpu = Cpu {
+            decimal_mode: true,
+            ..Default::default()
+        };
+
+        cpu.registers.accumulator = accumulator;
+        cpu.registers.status.set_decimal(true);
+        cpu.registers.status.set_carry(carry);
+
+        cpu
+    }
+
+    #[test]
+    fn decimal_mode_adds_bcd() {
+        let mut cpu = decimal_cpu(0x58, true);
+        let mut bus = FlatRam::default();
+        bus.load(0x0000, &[0x46]);
+
+        adc(&mut cpu, &mut bus, &AddrMode::Immediate);
+
+        assert_eq!(cpu.registers.accumulator, 0x05);
+        assert!(cpu.registers.status.carry());
+    }
+
+    #[test]
+    fn decimal_mode_subtracts_bcd() {
+        let mut cpu = decimal_cpu(0x00, true);
+        let mut bus = FlatRam::default();
+        bus.load(0x0000, &[0x01]);
+
+        sbc(&mut cpu, &mut bus, &AddrMode::Immediate);
+
+        assert_eq!(cpu.registers.accumulator, 0x99);
+        assert!(!cpu.registers.status.carry());
+    }
+
+    #[test]
+    fn decimal_flag_is_ignored_on_the_2a03() {
+        let mut cpu = decimal_cpu(0x09, false);
+        cpu.decimal_mode = false;
+        let mut bus = FlatRam::default();
+        bus.load(0x0000, &[0x01]);
+
+        adc(&mut cpu, &mut bus, &AddrMode::Immediate);
+
+        assert_eq!(cpu.registers.accumulator, 0x0A);
+    }
+
+    #[allow(dead_code)]
+    fn unstable_cpu(unstable_opcodes: UnstableOpcodes) -> Cpu {
+        let mut cpu = Cpu {
+            unstable_opcodes,
+            ..Default::default()
+        };
+
+        cpu.registers.accumulator = 0x12;
+        cpu.registers.x = 0x3C;
+
+        cpu
+    }
+
+    #[test]
+    fn magic_constants_are_configurable() {
+        let mut bus = FlatRam::default();
+        bus.load(0x0000, &[0xF7, 0xF7]);
+
+        let mut cpu = unstable_cpu(UnstableOpcodes::RP2A03);
+        xaa(&mut cpu, &mut bus, &AddrMode::Immediate);
+        assert_eq!(cpu.registers.accumulator, 0x34);
+
+        let mut cpu = unstable_cpu(UnstableOpcodes::SINGLE_STEP_TESTS);
+        lxa(&mut cpu, &mut bus, &AddrMode::Immediate);
+        assert_eq!(cpu.registers.accumulator, 0xF6);
+        assert_eq!(cpu.registers.x, 0xF6);
+    }
+
+    #[test]
+    fn high_byte_glitches_are_configurable() {
+        let mut bus = FlatRam::default();
+        bus.load(0x0000, &[0xF0, 0x12]);
+
+        let mut cpu = unstable_cpu(UnstableOpcodes::default());
+        cpu.registers.y = 0x20;
+        sxa(&mut cpu, &mut bus, &AddrMode::AbsoluteY);
+        assert_eq!(bus.memory[0x1010], 0x10);
+        assert_eq!(bus.memory[0x1310], 0x00);
+
+        let mut cpu = unstable_cpu(UnstableOpcodes {
+            and_high_byte: false,
+            page_cross_address: false,
+            ..Default::default()
+        });
+        cpu.registers.y = 0x20;
+        sxa(&mut cpu, &mut bus, &AddrMode::AbsoluteY);
+        assert_eq!(bus.memory[0x1310], 0x3C);
     }
 }
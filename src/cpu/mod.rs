@@ -1,9 +1,11 @@
-use crate::{ppu::PPU, NES};
+use crate::{diagnostics::DiagnosticKind, ppu::PPU, Interrupt, NES};
 
+pub mod assembler;
+pub mod flat_ram;
 pub(crate) mod instructions;
 pub(crate) mod registers;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum AddrMode {
     Implied,
     Accumulator,
@@ -20,132 +22,545 @@ pub enum AddrMode {
     IndirectY,
 }
 
-pub trait CPU {
-    fn cpu_clock(&mut self) -> usize;
-    fn cpu_read(&mut self, addr: u16) -> u8;
-    fn cpu_write(&mut self, addr: u16, data: u8);
-    fn cpu_read_u16(&mut self, addr: u16) -> u16 {
-        let low = self.cpu_read(addr);
-        let high = self.cpu_read(addr + 1);
+// Anything the 6502 can be wired up to. Every read or write is a CPU cycle,
+// tick is called at the start of each one before the access happens.
+pub trait Bus {
+    fn tick(&mut self);
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+    // Reads memory without side effects or spending a cycle, for debugging
+    fn peek(&self, addr: u16) -> u8;
+    fn peek_u16(&self, addr: u16) -> u16 {
+        let low = self.peek(addr);
+        let high = self.peek(addr.wrapping_add(1));
 
         u16::from_le_bytes([low, high])
     }
-    fn cpu_write_u16(&mut self, addr: u16, data: u16) {
-        let [low, high] = data.to_le_bytes();
 
-        self.cpu_write(addr, low);
-        self.cpu_write(addr + 1, high);
+    // The interrupt inputs, sampled at the start of every cycle. NMI is edge
+    // triggered so the bus latches it until the CPU acknowledges it.
+    fn nmi_pending(&mut self) -> bool {
+        false
+    }
+    fn acknowledge_nmi(&mut self) {}
+    fn irq_asserted(&self) -> bool {
+        false
+    }
+}
+
+// The illegal opcodes that depend on analog effects inside the chip. They vary
+// between revisions, between chips of the same revision and even with
+// temperature, so each test suite settles on its own answer.
+//
+// XAA ($8B) does A = (A | ane_magic) & X & #imm and LXA ($AB) does
+// A = X = (A | lxa_magic) & #imm. LAS ($BB) is stable everywhere so it has no
+// setting.
+//
+// The stores SHA ($93, $9F), SHX ($9E), SHY ($9C) and TAS ($9B) have two
+// glitches that can be turned off separately:
+//
+// - and_high_byte ANDs the stored register with the high byte of the base
+//   address plus one. With it off the register is stored as it is, which is
+//   what the console does when a DMA halts the CPU just before the write.
+// - page_cross_address replaces the high byte of the address with the stored
+//   value when indexing crosses a page. With it off the write goes to the
+//   address a plain STA would use, which is what games need if they were only
+//   ever tested on clone CPUs or emulators that don't have the glitch.
+//
+// Which suites the presets have been checked against is noted on each one.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct UnstableOpcodes {
+    pub ane_magic: u8,
+    pub lxa_magic: u8,
+    pub and_high_byte: bool,
+    pub page_cross_address: bool,
+}
+
+impl UnstableOpcodes {
+    // The default. $FF for both constants and both store glitches, following
+    // the nesdev wiki's notes on the 2A03. Unverified: no NES test ROM that
+    // exercises these opcodes is in the tree, and the constant differs between
+    // consoles, so the choice of $FF is only a guess. Only the
+    // SINGLE_STEP_TESTS preset below is backed by a suite.
+    pub const RP2A03: Self = Self {
+        ane_magic: 0xFF,
+        lxa_magic: 0xFF,
+        and_high_byte: true,
+        page_cross_address: true,
+    };
+
+    // What the SingleStepTests nes6502 corpus expects, $EE for both constants
+    // and both store glitches. tests/single_step.rs runs with this.
+    pub const SINGLE_STEP_TESTS: Self = Self {
+        ane_magic: 0xEE,
+        lxa_magic: 0xEE,
+        ..Self::RP2A03
+    };
+}
+
+impl Default for UnstableOpcodes {
+    fn default() -> Self {
+        Self::RP2A03
+    }
+}
+
+// Where a KIL opcode stopped the CPU
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Jam {
+    pub program_counter: u16,
+    pub opcode: u8,
+}
+
+#[derive(Default)]
+pub struct Cpu {
+    pub registers: registers::CpuRegisters,
+    // The 2A03 keeps the decimal flag but has no BCD circuitry, other 6502s can
+    // turn it on
+    pub decimal_mode: bool,
+    pub unstable_opcodes: UnstableOpcodes,
+    nmi_polled: bool,
+    interrupt_polled: bool,
+    jam: Option<Jam>,
+    jammed_cycles: usize,
+}
+
+impl Cpu {
+    pub fn reset<B: Bus>(&mut self, bus: &mut B) {
+        self.registers.accumulator = 0;
+        self.registers.x = 0;
+        self.registers.y = 0;
+        self.registers.stack_pointer = 0xFD;
+
+        self.registers.status.set_bits(0b0010_0100);
+
+        self.nmi_polled = false;
+        self.interrupt_polled = false;
+        self.jam = None;
+
+        // The reset sequence takes 7 cycles, the last two fetch the vector
+        for _ in 0..5 {
+            self.read(bus, self.registers.program_counter);
+        }
+
+        self.registers.program_counter = self.read_u16(bus, 0xFFFC);
+    }
+
+    // Runs a whole instruction, every bus access is its own cycle so the rest of
+    // the system is kept in step
+    pub fn clock<B: Bus>(&mut self, bus: &mut B) {
+        if self.jam.is_some() {
+            self.jammed_cycle(bus);
+            return;
+        }
+
+        let opcode = self.fetch(bus);
+
+        self.execute_instruction(bus, opcode);
     }
-    fn stack_push(&mut self, data: u8);
-    fn stack_pop(&mut self) -> u8;
-    fn stack_push_u16(&mut self, data: u16) {
+
+    pub fn try_interrupt<B: Bus>(&mut self, bus: &mut B) {
+        if !self.interrupt_polled || self.jam.is_some() {
+            return;
+        }
+
+        self.read(bus, self.registers.program_counter);
+        self.read(bus, self.registers.program_counter);
+
+        self.interrupt_sequence(bus, false);
+    }
+
+    // Set once a KIL opcode has run, only a reset gets the CPU going again
+    pub fn jammed(&self) -> Option<Jam> {
+        self.jam
+    }
+
+    pub(crate) fn jam(&mut self, program_counter: u16, opcode: u8) {
+        self.jam = Some(Jam {
+            program_counter,
+            opcode,
+        });
+        self.jammed_cycles = 0;
+    }
+
+    // A jammed CPU still drives the bus every cycle, it reads $FFFF, $FFFE
+    // twice and then sits on $FFFF. Interrupts are ignored.
+    fn jammed_cycle<B: Bus>(&mut self, bus: &mut B) {
+        let addr = match self.jammed_cycles {
+            1 | 2 => 0xFFFE,
+            _ => 0xFFFF,
+        };
+
+        self.read(bus, addr);
+        self.jammed_cycles = self.jammed_cycles.saturating_add(1);
+    }
+
+    // Polls the interrupt lines at the start of every cycle so the CPU acts on
+    // them as they were at the end of the second to last cycle of an instruction
+    pub fn tick<B: Bus>(&mut self, bus: &mut B) {
+        self.nmi_polled = bus.nmi_pending();
+        self.interrupt_polled =
+            self.nmi_polled || (bus.irq_asserted() && !self.registers.status.interrupt_disable());
+
+        bus.tick();
+    }
+
+    pub fn read<B: Bus>(&mut self, bus: &mut B, addr: u16) -> u8 {
+        self.tick(bus);
+        bus.read(addr)
+    }
+
+    pub fn write<B: Bus>(&mut self, bus: &mut B, addr: u16, data: u8) {
+        self.tick(bus);
+        bus.write(addr, data)
+    }
+
+    pub fn read_u16<B: Bus>(&mut self, bus: &mut B, addr: u16) -> u16 {
+        let low = self.read(bus, addr);
+        let high = self.read(bus, addr.wrapping_add(1));
+
+        u16::from_le_bytes([low, high])
+    }
+
+    pub fn fetch<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let data = self.read(bus, self.registers.program_counter);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
+
+        data
+    }
+
+    pub fn fetch_u16<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let low = self.fetch(bus);
+        let high = self.fetch(bus);
+
+        u16::from_le_bytes([low, high])
+    }
+
+    pub fn stack_push<B: Bus>(&mut self, bus: &mut B, data: u8) {
+        self.write(bus, 0x0100 + self.registers.stack_pointer as u16, data);
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(1);
+    }
+
+    pub fn stack_pop<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_add(1);
+        self.read(bus, 0x0100 + self.registers.stack_pointer as u16)
+    }
+
+    pub fn stack_push_u16<B: Bus>(&mut self, bus: &mut B, data: u16) {
         let [low, high] = data.to_le_bytes();
 
-        self.stack_push(high);
-        self.stack_push(low);
+        self.stack_push(bus, high);
+        self.stack_push(bus, low);
     }
-    fn stack_pop_u16(&mut self) -> u16 {
-        let low = self.stack_pop();
-        let high = self.stack_pop();
+
+    pub fn stack_pop_u16<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let low = self.stack_pop(bus);
+        let high = self.stack_pop(bus);
 
         u16::from_le_bytes([low, high])
     }
-    fn execute_instruction(&mut self, opcode: u8) -> (u16, usize);
-}
 
-impl CPU for NES {
-    fn cpu_clock(&mut self) -> usize {
-        if self.cpu_cycles == 0 {
-            let opcode = self.cpu_read(self.cpu_registers.program_counter);
+    fn execute_instruction<B: Bus>(&mut self, bus: &mut B, opcode: u8) {
+        let instruction = &instructions::Instructions::<B>::TABLE[opcode as usize];
+
+        // Single byte instructions read the next byte and throw it away
+        if let AddrMode::Implied | AddrMode::Accumulator = instruction.mode {
+            self.read(bus, self.registers.program_counter);
+        }
+
+        instruction.execute(self, bus);
+    }
 
-            self.cpu_registers.program_counter += 1;
+    // Pushes the return address and status then jumps through the vector. An NMI
+    // that arrives before the status is pushed hijacks a BRK or IRQ, which then
+    // runs the NMI handler instead.
+    pub(crate) fn interrupt_sequence<B: Bus>(&mut self, bus: &mut B, brk: bool) {
+        self.stack_push_u16(bus, self.registers.program_counter);
 
-            let old_program_counter = self.cpu_registers.program_counter;
+        let interrupt = if self.nmi_polled {
+            bus.acknowledge_nmi();
+            Interrupt::NMI
+        } else {
+            Interrupt::IRQ
+        };
 
-            let (length, cycles) = self.execute_instruction(opcode);
+        let mut flag = self.registers.status.clone();
+        flag.set_b(if brk { 0b11 } else { 0b10 });
 
-            if old_program_counter == self.cpu_registers.program_counter {
-                self.cpu_registers.program_counter += length;
+        self.stack_push(bus, flag.bits());
+        self.registers.status.set_interrupt_disable(true);
+
+        self.registers.program_counter = self.read_u16(bus, interrupt.address());
+        self.interrupt_polled = false;
+    }
+
+    // Fetches the operands of the current instruction, spending a cycle per bus
+    // access, and returns the address and if a page boundary was crossed. The
+    // fix-up cycle of indexed modes is left to the instruction since it depends
+    // on whether it reads or writes.
+    pub fn operating_address<B: Bus>(&mut self, bus: &mut B, mode: &AddrMode) -> (u16, bool) {
+        match mode {
+            AddrMode::Implied => panic!("Implied addressing mode has no operating address as it is implied"),
+            AddrMode::Accumulator => panic!("Accumulator addressing mode has no operating address as it operates on the accumulator"),
+            AddrMode::Immediate => {
+                let addr = self.registers.program_counter;
+                self.registers.program_counter = addr.wrapping_add(1);
+
+                (addr, false)
+            }
+            AddrMode::ZeroPage => {
+                let addr = self.fetch(bus) as u16;
+                (addr, false)
+            }
+            AddrMode::ZeroPageX => {
+                let base = self.fetch(bus);
+                self.read(bus, base as u16);
+
+                (base.wrapping_add(self.registers.x) as u16, false)
             }
+            AddrMode::ZeroPageY => {
+                let base = self.fetch(bus);
+                self.read(bus, base as u16);
 
-            self.cpu_cycles += cycles;
-            self.clock_count += cycles;
+                (base.wrapping_add(self.registers.y) as u16, false)
+            }
+            AddrMode::Relative => {
+                let offset = self.fetch(bus) as i8;
+                let old_addr = self.registers.program_counter;
+                let addr = old_addr.wrapping_add(offset as u16);
+
+                (addr, page_crossed(old_addr, addr))
+            }
+            AddrMode::Absolute => {
+                let addr = self.fetch_u16(bus);
+                (addr, false)
+            }
+            AddrMode::AbsoluteX => {
+                let old_addr = self.fetch_u16(bus);
+                let addr = old_addr.wrapping_add(self.registers.x as u16);
+
+                (addr, page_crossed(old_addr, addr))
+            }
+            AddrMode::AbsoluteY => {
+                let old_addr = self.fetch_u16(bus);
+                let addr = old_addr.wrapping_add(self.registers.y as u16);
 
-            return cycles;
+                (addr, page_crossed(old_addr, addr))
+            }
+            AddrMode::Indirect => {
+                let old_addr = self.fetch_u16(bus);
+
+                let addr = if old_addr & 0x00FF == 0x00FF {
+                    let low = self.read(bus, old_addr);
+                    let high = self.read(bus, old_addr & 0xFF00);
+
+                    u16::from_le_bytes([low, high])
+                } else {
+                    self.read_u16(bus, old_addr)
+                };
+
+                (addr, false)
+            }
+            AddrMode::IndirectX => {
+                let zero_page_addr = self.fetch(bus);
+                self.read(bus, zero_page_addr as u16);
+
+                let pointer = zero_page_addr.wrapping_add(self.registers.x);
+                let low = self.read(bus, pointer as u16);
+                let high = self.read(bus, pointer.wrapping_add(1) as u16);
+
+                let addr = u16::from_le_bytes([low, high]);
+
+                (addr, false)
+            }
+            AddrMode::IndirectY => {
+                let zero_page_addr = self.fetch(bus);
+                let low = self.read(bus, zero_page_addr as u16);
+                let high = self.read(bus, zero_page_addr.wrapping_add(1) as u16);
+
+                let old_addr = u16::from_le_bytes([low, high]);
+                let addr = old_addr.wrapping_add(self.registers.y as u16);
+
+                (addr, page_crossed(old_addr, addr))
+            }
+        }
+    }
+}
+
+fn page_crossed(old_addr: u16, new_addr: u16) -> bool {
+    old_addr & 0xFF00 != new_addr & 0xFF00
+}
+
+impl Bus for NES {
+    fn tick(&mut self) {
+        self.clock_count += 1;
+
+        if self.advance_master_clock() {
+            self.frame_complete = true;
         }
+    }
 
-        self.cpu_cycles -= 1;
-        return 0;
+    fn read(&mut self, addr: u16) -> u8 {
+        if let Some(page) = self.oam_dma_page.take() {
+            self.run_oam_dma(page, addr);
+        }
+
+        self.read_bus(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.write_bus(addr, data)
     }
 
-    fn cpu_read(&mut self, addr: u16) -> u8 {
+    fn peek(&self, addr: u16) -> u8 {
         match addr {
+            0x0000..=0x1FFF => self.cpu_ram[(addr & 0b00000111_11111111) as usize],
+            0x2000..=0x3FFF => self.ppu_open_bus,
+            0x4000..=0x5FFF => self.cpu_data_bus,
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                if self.prg_rom.is_empty() {
+                    self.cpu_data_bus
+                } else {
+                    self.prg_rom[(addr - 0x8000) as usize % self.prg_rom.len()]
+                }
+            }
+        }
+    }
+
+    fn nmi_pending(&mut self) -> bool {
+        self.detect_nmi_edge();
+        self.nmi_pending
+    }
+
+    fn acknowledge_nmi(&mut self) {
+        self.nmi_pending = false;
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.irq_line != 0
+    }
+}
+
+impl NES {
+    // The CPU is taken out of the NES while it runs so that it can borrow the
+    // rest of it as its bus
+    pub(crate) fn with_cpu<R>(&mut self, f: impl FnOnce(&mut Cpu, &mut NES) -> R) -> R {
+        let mut cpu = core::mem::take(&mut self.cpu);
+        let result = f(&mut cpu, self);
+        self.cpu = cpu;
+
+        result
+    }
+
+    // Runs a whole instruction and returns the number of cycles it took
+    pub fn cpu_clock(&mut self) -> usize {
+        let start = self.clock_count;
+
+        self.with_cpu(|cpu, nes| cpu.clock(nes));
+
+        self.clock_count - start
+    }
+
+    pub fn cpu_tick(&mut self) {
+        self.with_cpu(|cpu, nes| cpu.tick(nes))
+    }
+
+    pub fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.with_cpu(|cpu, nes| cpu.read(nes, addr))
+    }
+
+    pub fn cpu_write(&mut self, addr: u16, data: u8) {
+        self.with_cpu(|cpu, nes| cpu.write(nes, addr, data))
+    }
+
+    // OAM DMA halts the CPU on a read, which it keeps repeating until the copy is
+    // done. Reads happen on even (get) cycles and writes on odd (put) cycles, so
+    // the copy takes 513 cycles, or 514 when it has to wait a cycle to line up.
+    // The CPU has already spent the first cycle of the halted read.
+    //
+    // TODO: DMC DMA isn't emulated since there's no APU yet. Once it is, a DMC
+    // fetch that lands during the copy steals its get cycle from OAM DMA and
+    // usually adds two cycles to it, which this loop will need to make room for.
+    fn run_oam_dma(&mut self, page: u8, halted_addr: u16) {
+        self.read_bus(halted_addr);
+
+        if self.clock_count & 1 == 0 {
+            self.tick();
+            self.read_bus(halted_addr);
+        }
+
+        for low in 0..=255u8 {
+            self.tick();
+            let data = self.read_bus(u16::from_le_bytes([low, page]));
+
+            self.tick();
+            self.ppu_write_oam_data(data);
+        }
+
+        self.tick();
+    }
+
+    fn read_bus(&mut self, addr: u16) -> u8 {
+        let data = match addr {
             0x0000..=0x1FFF => {
                 let mirrored_addr = addr & 0b00000111_11111111;
 
                 self.cpu_ram[mirrored_addr as usize]
             }
-            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 | 0x4014 => {
-                // panic!("attempted to read from write-only PPU address {:x}", addr);
-                0
-            }
-            0x2002 => self.ppu_read_status(),
-            0x2004 => self.ppu_read_oam_data(),
-            0x2007 => self.ppu_read(),
+            0x2000..=0x3FFF => match addr & 0b00100000_00000111 {
+                0x2002 => self.ppu_read_status(),
+                0x2004 => self.ppu_read_oam_data(),
+                0x2007 => self.ppu_read(),
+                _ => self.ppu_open_bus(),
+            },
             0x4000..=0x4015 => {
-                // panic!("APU and I/O registers are not implemented yet!")
-                0
-            }
-            0x4016 => self.controller.read(),
-            0x4017 => 0,
-            0x2008..=0x3FFF => {
-                let mirrored_down_address = addr & 0b00100000_00000111;
-                self.cpu_read(mirrored_down_address)
+                // APU registers are write-only or not implemented yet
+                self.cpu_data_bus
             }
+            // Only the low bits are driven by the controller port, the rest is open bus
+            0x4016 => (self.cpu_data_bus & 0xE0) | (self.controller.read() & 0x1F),
+            0x4017 => self.cpu_data_bus & 0xE0,
+            0x4018..=0x5FFF => self.cpu_data_bus,
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
             0x8000..=0xFFFF => {
-                let mut addr = addr - 0x8000;
-
-                if self.prg_rom.len() == 0x4000 && addr >= 0x4000 {
-                    addr %= 0x4000;
+                if self.prg_rom.is_empty() {
+                    self.cpu_data_bus
+                } else {
+                    self.prg_rom[(addr - 0x8000) as usize % self.prg_rom.len()]
                 }
-
-                self.prg_rom[addr as usize]
             }
-            _ => {
-                panic!("Invalid CPU read address: {:#06X}", addr);
-            }
-        }
+        };
+
+        self.cpu_data_bus = data;
+
+        data
     }
 
-    fn cpu_write(&mut self, addr: u16, data: u8) {
+    fn write_bus(&mut self, addr: u16, data: u8) {
+        self.cpu_data_bus = data;
+
         match addr {
             0x0000..=0x1FFF => {
                 let mirrored_addr = addr & 0b00000111_11111111;
 
                 self.cpu_ram[mirrored_addr as usize] = data;
             }
-            0x2000 => self.ppu_write_control(data),
-            0x2001 => self.ppu_write_mask(data),
-            0x2002 => panic!("attempt to write to ppu status register.. dont do that"),
-            0x2003 => self.ppu_write_oam_address(data),
-            0x2004 => self.ppu_write_oam_data(data),
-            0x2005 => self.ppu_write_scroll(data),
-            0x2006 => self.ppu_write_address(data),
-            0x2007 => self.ppu_write(data),
-            0x2008..=0x3FFF => {
-                let mirrored_down_address = addr & 0b00100000_00000111;
-                self.cpu_write(mirrored_down_address, data);
-            }
-            0x4014 => {
-                let mut buffer = [0u8; 256];
-                let high = (data as u16) << 8;
-
-                for i in 0..256u16 {
-                    buffer[i as usize] = self.cpu_read(high + i);
-                }
+            0x2000..=0x3FFF => {
+                self.ppu_refresh_open_bus(data, 0xFF);
 
-                self.ppu_write_oam_dma(&buffer)
+                match addr & 0b00100000_00000111 {
+                    0x2000 => self.ppu_write_control(data),
+                    0x2001 => self.ppu_write_mask(data),
+                    0x2002 => self.report(DiagnosticKind::PpuStatusWrite, addr, data),
+                    0x2003 => self.ppu_write_oam_address(data),
+                    0x2004 => self.ppu_write_oam_data(data),
+                    0x2005 => self.ppu_write_scroll(data),
+                    0x2006 => self.ppu_write_address(data),
+                    _ => self.ppu_write(data),
+                }
             }
+            // The copy starts once the CPU reaches its next read cycle
+            0x4014 => self.oam_dma_page = Some(data),
             0x4000..=0x4015 => {
                 // panic!("APU and I/O registers are not implemented yet!")
             }
@@ -156,33 +571,195 @@ impl CPU for NES {
             0x4018..=0x401F => {
                 // panic!("APU and I/O functionality that is normally disabled")
             }
-            0x4020..=0x7999 => {
-                // panic!("PRG RAM and mapper registers")
-            }
-            0x8000..=0xFFFF => {
-                panic!("Cannot write to PRG ROM!")
-            }
-            _ => {
-                panic!("Invalid CPU write address: {:#06X}", addr);
+            0x4020..=0x5FFF => {
+                // expansion area, nothing is mapped here
             }
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = data,
+            0x8000..=0xFFFF => self.report(DiagnosticKind::PrgRomWrite, addr, data),
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use flat_ram::FlatRam;
+
+    #[test]
+    fn unmapped_reads_return_the_last_bus_value() {
+        let mut nes = NES::default();
+
+        nes.cpu_write(0x0010, 0x5A);
+
+        assert_eq!(nes.cpu_read(0x5000), 0x5A);
+        assert_eq!(nes.cpu_read(0x4000), 0x5A);
+        assert_eq!(nes.cpu_read(0x4018), 0x5A);
+    }
+
+    #[test]
+    fn controller_ports_drive_only_the_low_bits() {
+        let mut nes = NES::default();
+
+        nes.controller.button_state.set_a(true);
+        nes.cpu_write(0x0010, 0x40);
+        nes.cpu_read(0x0010);
+
+        assert_eq!(nes.cpu_read(0x4016), 0x41);
+        assert_eq!(nes.cpu_read(0x4017), 0x40);
+    }
+
+    #[test]
+    fn prg_ram_is_mapped() {
+        let mut nes = NES::default();
+
+        nes.cpu_write(0x6000, 0x12);
+        nes.cpu_write(0x7FFF, 0x34);
+
+        assert_eq!(nes.cpu_read(0x6000), 0x12);
+        assert_eq!(nes.cpu_read(0x7FFF), 0x34);
+    }
+
+    #[test]
+    fn every_address_is_readable() {
+        let mut nes = NES::default();
+
+        for addr in 0..=0xFFFF {
+            nes.cpu_read(addr);
+        }
+
+        nes.insert_cart(crate::cartridge::test::test_rom(None));
+
+        for addr in 0..=0xFFFF {
+            nes.cpu_read(addr);
         }
     }
 
-    fn stack_push(&mut self, data: u8) {
-        self.cpu_write(0x0100 + self.cpu_registers.stack_pointer as u16, data);
-        self.cpu_registers.stack_pointer = self.cpu_registers.stack_pointer.wrapping_sub(1);
+    #[allow(dead_code)]
+    fn dma_nes(clock_count: usize) -> NES {
+        let mut nes = NES {
+            clock_count,
+            oam_dma_page: Some(0x02),
+            ..Default::default()
+        };
+
+        for i in 0..256 {
+            nes.cpu_ram[0x200 + i] = i as u8;
+        }
+
+        nes
     }
 
-    fn stack_pop(&mut self) -> u8 {
-        self.cpu_registers.stack_pointer = self.cpu_registers.stack_pointer.wrapping_add(1);
-        self.cpu_read(0x0100 + self.cpu_registers.stack_pointer as u16)
+    #[test]
+    fn oam_dma_copies_the_page() {
+        let mut nes = dma_nes(0);
+
+        nes.cpu_read(0x0000);
+
+        for i in 0..256 {
+            assert_eq!(nes.oam_data[i], i as u8);
+        }
     }
 
-    fn execute_instruction(&mut self, opcode: u8) -> (u16, usize) {
-        let instruction = &instructions::INSTRUCTIONS_TABLE[opcode as usize];
+    #[test]
+    fn oam_dma_halts_for_513_or_514_cycles() {
+        let mut nes = dma_nes(10);
+
+        nes.cpu_read(0x0000);
+
+        assert_eq!(nes.clock_count, 10 + 513 + 1);
+
+        // The write landed on an odd cycle so the first read has to wait
+        let mut nes = dma_nes(11);
+
+        nes.cpu_read(0x0000);
+
+        assert_eq!(nes.clock_count, 11 + 514 + 1);
+    }
+
+    #[test]
+    fn oam_dma_starts_on_the_next_read() {
+        let mut nes = NES::default();
+
+        nes.cpu_write(0x4014, 0x02);
+        nes.cpu_write(0x0000, 0x00);
+
+        assert_eq!(nes.clock_count, 2);
+
+        nes.cpu_read(0x0000);
+
+        assert!(nes.clock_count > 2 + 513);
+    }
+
+    #[allow(dead_code)]
+    fn flat_ram(program: &[u8]) -> (Cpu, FlatRam) {
+        let mut bus = FlatRam::default();
+
+        bus.load(0x0200, program);
+        bus.load(0xFFFC, &[0x00, 0x02]);
+
+        let mut cpu = Cpu::default();
+        cpu.reset(&mut bus);
+
+        (cpu, bus)
+    }
+
+    #[test]
+    fn runs_on_a_flat_ram_bus() {
+        // LDX #$03, loop: DEX, BNE loop, STX $10
+        let (mut cpu, mut bus) = flat_ram(&[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x86, 0x10]);
+        bus.memory[0x10] = 0xFF;
+
+        for _ in 0..8 {
+            cpu.clock(&mut bus);
+        }
+
+        assert_eq!(bus.memory[0x10], 0x00);
+        assert_eq!(cpu.registers.program_counter, 0x0207);
+        assert_eq!(bus.cycles, 7 + 2 + (2 + 3) * 2 + 2 + 2 + 3);
+    }
+
+    #[test]
+    fn brk_on_a_flat_ram_bus_uses_irq_vector() {
+        let (mut cpu, mut bus) = flat_ram(&[0x00, 0xFF]);
+        bus.memory[0xFFFE..].copy_from_slice(&[0x34, 0x12]);
+
+        cpu.clock(&mut bus);
+
+        assert_eq!(cpu.registers.program_counter, 0x1234);
+        assert_eq!(bus.memory[0x01FD], 0x02);
+        assert_eq!(bus.memory[0x01FC], 0x02);
+        assert_eq!(bus.cycles, 7 + 7);
+    }
+
+    #[test]
+    fn kil_jams_until_reset() {
+        // LDX #$01, KIL, INX
+        let (mut cpu, mut bus) = flat_ram(&[0xA2, 0x01, 0x02, 0xE8]);
+
+        cpu.clock(&mut bus);
+        cpu.clock(&mut bus);
+
+        assert_eq!(
+            cpu.jammed(),
+            Some(Jam {
+                program_counter: 0x0202,
+                opcode: 0x02,
+            })
+        );
+
+        for _ in 0..10 {
+            cpu.clock(&mut bus);
+        }
+
+        assert_eq!(cpu.registers.x, 0x01);
+        assert_eq!(cpu.registers.program_counter, 0x0203);
+        assert_eq!(bus.cycles, 7 + 2 + 2 + 10);
 
-        let cycles = instruction.execute(self);
+        cpu.reset(&mut bus);
+        cpu.clock(&mut bus);
 
-        (instruction.size(), cycles)
+        assert_eq!(cpu.jammed(), None);
+        assert_eq!(cpu.registers.x, 0x01);
     }
 }
@@ -0,0 +1,186 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::NES;
+
+// Beyond this the oldest reports are kept and new ones dropped, so a frontend
+// that never takes them doesn't grow the queue forever
+const MAX_QUEUED: usize = 256;
+
+// Things games do that the console quietly puts up with. They're emulated the
+// way the hardware behaves, and reported when diagnostics are turned on.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DiagnosticKind {
+    // $2002 is read only, the write just refreshes the PPU I/O latch
+    PpuStatusWrite,
+    // There's no mapper with registers in $8000-$FFFF, so the write is lost
+    PrgRomWrite,
+    // CHR ROM can't be written through $2007
+    ChrRomWrite,
+    // A KIL opcode stopped the CPU until the next reset. The address is where
+    // it was and the data is the opcode.
+    CpuJam,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    // The instruction that caused it
+    pub program_counter: u16,
+    // The CPU address for bus writes, the PPU address for $2007 writes
+    pub addr: u16,
+    pub data: u8,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.kind {
+            DiagnosticKind::PpuStatusWrite => "write to PPU status",
+            DiagnosticKind::PrgRomWrite => "write to PRG ROM",
+            DiagnosticKind::ChrRomWrite => "write to CHR ROM",
+            DiagnosticKind::CpuJam => {
+                return write!(
+                    f,
+                    "{:04X}: CPU jammed by opcode {:02X}",
+                    self.addr, self.data
+                )
+            }
+        };
+
+        write!(
+            f,
+            "{:04X}: {} at {:04X} = {:02X}",
+            self.program_counter, what, self.addr, self.data
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct Diagnostics {
+    enabled: bool,
+    // Start of the instruction being run
+    pub(crate) program_counter: u16,
+    queue: Vec<Diagnostic>,
+}
+
+impl NES {
+    // Diagnostics are off by default, nothing is recorded until they're enabled
+    pub fn set_diagnostics_enabled(&mut self, enabled: bool) {
+        self.diagnostics.enabled = enabled;
+    }
+
+    // Everything reported since the last call, oldest first
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        core::mem::take(&mut self.diagnostics.queue)
+    }
+
+    pub(crate) fn report(&mut self, kind: DiagnosticKind, addr: u16, data: u8) {
+        let diagnostics = &mut self.diagnostics;
+
+        if !diagnostics.enabled || diagnostics.queue.len() >= MAX_QUEUED {
+            return;
+        }
+
+        diagnostics.queue.push(Diagnostic {
+            kind,
+            program_counter: diagnostics.program_counter,
+            addr,
+            data,
+        });
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::{cartridge, cpu::assembler::assemble};
+
+    #[allow(dead_code)]
+    fn run(source: &str, instructions: usize) -> NES {
+        let program = assemble(source).unwrap();
+        let mut nes = NES::from_cartridge(cartridge::test::test_rom(Some(program)));
+        nes.set_diagnostics_enabled(true);
+
+        for _ in 0..instructions {
+            nes.step_instruction();
+        }
+
+        nes
+    }
+
+    #[test]
+    fn bad_writes_are_reported_with_the_instruction() {
+        let mut nes = run(
+            "
+            LDA #$12
+            STA $2002
+            STA $8000
+            ",
+            3,
+        );
+
+        assert_eq!(
+            nes.take_diagnostics(),
+            [
+                Diagnostic {
+                    kind: DiagnosticKind::PpuStatusWrite,
+                    program_counter: 0x8002,
+                    addr: 0x2002,
+                    data: 0x12,
+                },
+                Diagnostic {
+                    kind: DiagnosticKind::PrgRomWrite,
+                    program_counter: 0x8005,
+                    addr: 0x8000,
+                    data: 0x12,
+                },
+            ]
+        );
+        assert_eq!(nes.take_diagnostics(), []);
+    }
+
+    #[test]
+    fn jam_is_reported_once() {
+        let mut nes = run(
+            "
+            NOP
+            KIL
+            ",
+            10,
+        );
+
+        assert_eq!(
+            nes.take_diagnostics(),
+            [Diagnostic {
+                kind: DiagnosticKind::CpuJam,
+                program_counter: 0x8001,
+                addr: 0x8001,
+                data: 0x02,
+            }]
+        );
+    }
+
+    #[test]
+    fn nothing_is_reported_when_disabled() {
+        let mut nes = run("STA $8000", 0);
+        nes.set_diagnostics_enabled(false);
+        nes.step_instruction();
+
+        assert_eq!(nes.take_diagnostics(), []);
+    }
+
+    #[test]
+    fn queue_is_bounded() {
+        let mut nes = run(
+            "
+        loop:
+            STA $8000
+            JMP loop
+            ",
+            2 * MAX_QUEUED + 10,
+        );
+
+        assert_eq!(nes.take_diagnostics().len(), MAX_QUEUED);
+    }
+}
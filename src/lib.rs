@@ -1,21 +1,33 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 pub mod cartridge;
 pub mod cpu;
+pub mod diagnostics;
 pub mod input;
+#[cfg(feature = "std")]
 pub mod logger;
 pub mod ppu;
+pub mod region;
 pub mod renderer;
+pub mod scheduler;
+pub mod test_rom;
 
-use cpu::{AddrMode, CPU};
+use alloc::{vec, vec::Vec};
+use cpu::Cpu;
 use ppu::PPU;
-use sdl2::sys::Screen;
 
 use crate::{
     cartridge::ScreenMirroring,
+    diagnostics::{DiagnosticKind, Diagnostics},
     input::Controller,
-    renderer::{palette, Frame, ViewPortRect},
+    region::Region,
+    renderer::{palette, Frame, SpritePixel, ViewPortRect, FRAME_HEIGHT, FRAME_WIDTH},
+    scheduler::Scheduler,
 };
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Interrupt {
     NMI,
     IRQ,
@@ -25,34 +37,64 @@ impl Interrupt {
     fn address(&self) -> u16 {
         match self {
             Interrupt::NMI => 0xFFFA,
-            Interrupt::IRQ => 0xFFFB,
+            Interrupt::IRQ => 0xFFFE,
         }
     }
 }
 
+// Everything that can pull the shared IRQ line low, it stays asserted until
+// every source has been acknowledged
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum IrqSource {
+    FrameCounter = 0b0001,
+    Dmc = 0b0010,
+    Mapper = 0b0100,
+    External = 0b1000,
+}
+
 pub struct NES {
     // cpu
     cpu_ram: [u8; 2048],
     prg_rom: Vec<u8>,
-    cpu_cycles: usize,
+    prg_ram: [u8; 0x2000],
+    cpu_data_bus: u8,
     clock_count: usize,
-    pub cpu_registers: cpu::registers::CpuRegisters,
+    pub cpu: Cpu,
 
     // ppu
     chr_rom: Vec<u8>,
+    chr_ram: bool,
     palette_table: [u8; 32],
-    ppu_vram: [u8; 2048],
+    // Only four-screen carts use the upper 2KB, which is on the cart
+    ppu_vram: [u8; 0x1000],
     oam_data: [u8; 256],
     mirroring: ScreenMirroring,
     ppu_cycles: usize,
     ppu_scanline: usize,
     ppu_read_buffer: u8,
+    ppu_odd_frame: bool,
+    ppu_warmed_up: bool,
+    ppu_vblank_suppressed: bool,
+    ppu_frame_count: usize,
+    ppu_open_bus: u8,
+    ppu_open_bus_refreshed: [usize; 8],
     pub ppu_registers: ppu::registers::PpuRegisters,
 
     // misc
-    next_interrupt: Option<Interrupt>,
-
+    region: Region,
+    scheduler: Scheduler,
+    irq_line: u8,
+    nmi_line: bool,
+    nmi_pending: bool,
+    oam_dma_page: Option<u8>,
+    diagnostics: Diagnostics,
+
+    frame_complete: bool,
     current_frame: Frame,
+    // Layers composited into current_frame, kept between frames so rendering
+    // doesn't allocate
+    background_layer: Vec<Option<u8>>,
+    sprite_layer: Vec<Option<SpritePixel>>,
     controller: Controller,
 }
 
@@ -61,225 +103,273 @@ impl Default for NES {
         Self {
             cpu_ram: [0; 2048],
             prg_rom: vec![],
-            cpu_cycles: 0,
+            prg_ram: [0; 0x2000],
+            cpu_data_bus: 0,
             clock_count: 0,
-            cpu_registers: cpu::registers::CpuRegisters::default(),
+            cpu: Cpu::default(),
 
             chr_rom: vec![],
+            chr_ram: false,
             palette_table: [0; 32],
-            ppu_vram: [0; 2048],
+            ppu_vram: [0; 0x1000],
             oam_data: [0; 256],
             mirroring: ScreenMirroring::Horizontal,
             ppu_cycles: 0,
             ppu_scanline: 0,
             ppu_read_buffer: 0,
+            ppu_odd_frame: false,
+            ppu_warmed_up: false,
+            ppu_vblank_suppressed: false,
+            ppu_frame_count: 0,
+            ppu_open_bus: 0,
+            ppu_open_bus_refreshed: [0; 8],
             ppu_registers: ppu::registers::PpuRegisters::default(),
 
-            next_interrupt: None,
+            region: Region::default(),
+            scheduler: Scheduler::default(),
+            irq_line: 0,
+            nmi_line: false,
+            nmi_pending: false,
+            oam_dma_page: None,
+            diagnostics: Diagnostics::default(),
 
+            frame_complete: false,
             current_frame: Frame::new(),
+            background_layer: vec![None; FRAME_WIDTH * FRAME_HEIGHT],
+            sprite_layer: vec![None; FRAME_WIDTH * FRAME_HEIGHT],
             controller: Controller::new(),
         }
     }
 }
 
 impl NES {
+    pub fn from_cartridge(cart: cartridge::Cartridge) -> Self {
+        let mut nes = NES::default();
+
+        nes.insert_cart(cart);
+        nes.reset();
+
+        nes
+    }
+
+    #[cfg(feature = "std")]
     pub fn start<F>(&mut self, rom_file: &str, mut render_callback: F)
     where
         F: FnMut(&Frame, &mut Controller),
     {
-        let cart = cartridge::Cartridge::load(rom_file);
+        let cart = cartridge::Cartridge::load(rom_file)
+            .unwrap_or_else(|err| panic!("unable to load {}: {}", rom_file, err));
         self.insert_cart(cart);
         self.reset();
-        self.cpu_registers.program_counter = 0xC000;
 
         loop {
-            if self.cpu_cycles == 0 {
-                // logger::log(self);
-            }
+            self.run_frame();
+            render_callback(&self.current_frame, &mut self.controller)
+        }
+    }
 
-            let cycles = self.cpu_clock();
+    // Runs until the PPU finishes the current frame, which is then in frame()
+    pub fn run_frame(&mut self) {
+        while !self.step() {}
+    }
 
-            if cycles > 0 {
-                let new_frame = self.ppu_clock(cycles);
+    // Runs a single instruction, and the interrupt after it if one was polled,
+    // returning the number of cycles spent
+    pub fn step_instruction(&mut self) -> usize {
+        let start = self.clock_count;
 
-                self.try_interrupt();
+        self.step();
 
-                if new_frame {
-                    self.render();
-                    render_callback(&self.current_frame, &mut self.controller)
-                }
+        self.clock_count - start
+    }
+
+    pub fn frame(&self) -> &Frame {
+        &self.current_frame
+    }
+
+    pub fn controller(&mut self) -> &mut Controller {
+        &mut self.controller
+    }
+
+    fn step(&mut self) -> bool {
+        // logger::log(self);
+
+        self.with_cpu(|cpu, nes| {
+            let running = cpu.jammed().is_none();
+            nes.diagnostics.program_counter = cpu.registers.program_counter;
+
+            cpu.clock(nes);
+            cpu.try_interrupt(nes);
+
+            if let Some(jam) = cpu.jammed().filter(|_| running) {
+                nes.report(DiagnosticKind::CpuJam, jam.program_counter, jam.opcode);
             }
+        });
+
+        if !self.frame_complete {
+            return false;
         }
+
+        self.frame_complete = false;
+        self.render();
+
+        true
     }
 
     pub fn reset(&mut self) {
-        self.cpu_registers.accumulator = 0;
-        self.cpu_registers.x = 0;
-        self.cpu_registers.y = 0;
-        self.cpu_registers.stack_pointer = 0xFD;
+        self.nmi_pending = false;
 
-        self.cpu_registers.status.set_bits(0b0010_0100);
-        self.cpu_registers.program_counter = self.cpu_read_u16(0xFFFC);
+        self.clock_count = 0;
+        self.ppu_cycles = 0;
+        self.scheduler.reset();
 
-        self.cpu_cycles = 7;
-        self.clock_count = 7;
-        self.ppu_cycles = 21;
+        self.with_cpu(|cpu, nes| cpu.reset(nes));
     }
 
     pub fn insert_cart(&mut self, cart: cartridge::Cartridge) {
         self.prg_rom = cart.prg_rom;
         self.chr_rom = cart.chr_rom;
+        self.chr_ram = cart.chr_ram;
+        self.mirroring = cart.screen_mirroring;
+        self.set_region(cart.region);
     }
 
-    // Returns the address and if a page boundary was crossed
-    pub fn get_operating_address(&mut self, mode: &AddrMode) -> (u16, bool) {
-        match mode {
-            AddrMode::Implied => panic!("Implied addressing mode has no operating address as it is implied"),
-            AddrMode::Accumulator => panic!("Accumulator addressing mode has no operating address as it operates on the accumulator"),
-            AddrMode::Immediate => {
-                let addr = self.cpu_registers.program_counter;
-                (addr, false)
-            },
-            _ => self.get_absolute_address(self.cpu_registers.program_counter, mode),
-        }
+    pub fn region(&self) -> Region {
+        self.region
     }
 
-    pub fn get_absolute_address(&mut self, addr: u16, mode: &AddrMode) -> (u16, bool) {
-        match mode {
-            AddrMode::ZeroPage => {
-                let addr = self.cpu_read(addr) as u16;
-                (addr, false)
-            }
-            AddrMode::ZeroPageX => {
-                let addr = self.cpu_read(addr).wrapping_add(self.cpu_registers.x) as u16;
-                (addr, false)
-            }
-            AddrMode::ZeroPageY => {
-                let addr = self.cpu_read(addr).wrapping_add(self.cpu_registers.y) as u16;
-                (addr, false)
-            }
-            AddrMode::Relative => {
-                let offset = self.cpu_read(addr) as u16;
-                let old_addr = addr;
-                let addr = old_addr.wrapping_add(1).wrapping_add(offset);
-
-                (addr, page_crossed(old_addr, addr))
-            }
-            AddrMode::Absolute => {
-                let addr = self.cpu_read_u16(addr);
-                (addr, false)
-            }
-            AddrMode::AbsoluteX => {
-                let old_addr = self.cpu_read_u16(addr);
-                let addr = old_addr.wrapping_add(self.cpu_registers.x as u16);
-
-                (addr, page_crossed(old_addr, addr))
-            }
-            AddrMode::AbsoluteY => {
-                let old_addr = self.cpu_read_u16(addr);
-                let addr = old_addr.wrapping_add(self.cpu_registers.y as u16);
-
-                (addr, page_crossed(old_addr, addr))
-            }
-            AddrMode::Indirect => {
-                let old_addr = self.cpu_read_u16(addr);
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.scheduler.cpu_divider = region.cpu_divider();
+        self.scheduler.ppu_divider = region.ppu_divider();
+    }
 
-                let addr = if old_addr & 0x00FF == 0x00FF {
-                    let low = self.cpu_read(old_addr);
-                    let high = self.cpu_read(old_addr & 0xFF00);
+    pub fn set_irq(&mut self, source: IrqSource, asserted: bool) {
+        if asserted {
+            self.irq_line |= source as u8;
+        } else {
+            self.irq_line &= !(source as u8);
+        }
+    }
 
-                    u16::from_le_bytes([low, high])
-                } else {
-                    self.cpu_read_u16(old_addr)
-                };
+    // NMI is edge triggered, it is latched when the PPU pulls the line and stays
+    // pending until serviced
+    pub(crate) fn detect_nmi_edge(&mut self) {
+        let line = self.ppu_nmi_output();
 
-                (addr, false)
-            }
-            AddrMode::IndirectX => {
-                let zero_page_addr = self.cpu_read(addr);
-                let pointer = zero_page_addr.wrapping_add(self.cpu_registers.x);
-                let low = self.cpu_read(pointer as u16);
-                let high = self.cpu_read(pointer.wrapping_add(1) as u16);
-
-                let addr = u16::from_le_bytes([low, high]);
+        if line && !self.nmi_line {
+            self.nmi_pending = true;
+        }
 
-                (addr, false)
-            }
-            AddrMode::IndirectY => {
-                let zero_page_addr = self.cpu_read(addr);
-                let low = self.cpu_read(zero_page_addr as u16);
-                let high = self.cpu_read(zero_page_addr.wrapping_add(1) as u16);
+        self.nmi_line = line;
+    }
 
-                let old_addr = u16::from_le_bytes([low, high]);
-                let addr = old_addr.wrapping_add(self.cpu_registers.y as u16);
+    fn render(&mut self) {
+        let mut background = core::mem::take(&mut self.background_layer);
+        let mut sprites = core::mem::take(&mut self.sprite_layer);
+        background.fill(None);
+        sprites.fill(None);
 
-                (addr, page_crossed(old_addr, addr))
-            }
-            _ => panic!("Invalid absolute addressing mode"),
+        if self.ppu_registers.mask.show_background() {
+            self.render_background(&mut background);
         }
-    }
 
-    fn try_interrupt(&mut self) {
-        if let None = self.next_interrupt {
-            return;
+        if self.ppu_registers.mask.show_sprite() {
+            self.render_sprites(&mut sprites);
         }
 
-        self.perform_interrupt();
-
-        self.next_interrupt = None;
-    }
+        let backdrop = self.palette_table[0];
+        let greyscale = self.ppu_registers.mask.greyscale();
+        let emphasis = self.ppu_registers.mask.emphasis();
 
-    fn perform_interrupt(&mut self) {
-        self.stack_push_u16(self.cpu_registers.program_counter);
-        let mut flag = self.cpu_registers.status.clone();
-        flag.set_b(0b01);
+        for y in 0..FRAME_HEIGHT {
+            for x in 0..FRAME_WIDTH {
+                let index = y * FRAME_WIDTH + x;
 
-        self.stack_push(*flag.into_bytes().first().unwrap());
-        self.cpu_registers.status.set_interrupt_disable(true);
+                let background = if x < 8 && !self.ppu_registers.mask.leftmost_8px_background() {
+                    None
+                } else {
+                    background[index]
+                };
 
-        self.cpu_cycles += 2;
-        self.clock_count += 2;
-        self.ppu_clock(2);
+                let sprite = if x < 8 && !self.ppu_registers.mask.leftmost_8px_sprite() {
+                    None
+                } else {
+                    sprites[index]
+                };
 
-        let interrupt = self.next_interrupt.as_ref().unwrap();
+                let color = match (background, sprite) {
+                    (None, None) => backdrop,
+                    (Some(color), None) => color,
+                    (None, Some(sprite)) => sprite.color,
+                    (Some(color), Some(sprite)) => {
+                        if sprite.behind_background {
+                            color
+                        } else {
+                            sprite.color
+                        }
+                    }
+                };
 
-        self.cpu_registers.program_counter = self.cpu_read_u16(interrupt.address());
-    }
+                self.current_frame.set_pixel(
+                    x,
+                    y,
+                    palette::output_color(color, greyscale, emphasis),
+                );
+            }
+        }
 
-    fn render(&mut self) {
-        self.render_background();
-        self.render_sprites();
+        self.background_layer = background;
+        self.sprite_layer = sprites;
     }
 
-    fn render_background(&mut self) {
-        let scroll_x = self.ppu_registers.scroll.scroll_x as usize;
-        let scroll_y = self.ppu_registers.scroll.scroll_y as usize;
-
-        let (first_nametable, second_nametable) = match (
+    // The VRAM offsets of the nametable selected in PPUCTRL and of the one
+    // scrolled into view next to it
+    fn name_table_offsets(&self) -> (usize, usize) {
+        match (
             self.mirroring.clone(),
             self.ppu_registers.control.name_table_address(),
         ) {
             (ScreenMirroring::Vertical, 0x2000)
             | (ScreenMirroring::Vertical, 0x2800)
             | (ScreenMirroring::Horizontal, 0x2000)
-            | (ScreenMirroring::Horizontal, 0x2400) => (
-                &self.ppu_vram.clone()[0..0x400],
-                &self.ppu_vram.clone()[0x400..0x800],
-            ),
+            | (ScreenMirroring::Horizontal, 0x2400) => (0, 0x400),
             (ScreenMirroring::Vertical, 0x2400)
             | (ScreenMirroring::Vertical, 0x2C00)
             | (ScreenMirroring::Horizontal, 0x2800)
-            | (ScreenMirroring::Horizontal, 0x2C00) => (
-                &self.ppu_vram.clone()[0x400..0x800],
-                &self.ppu_vram.clone()[0..0x400],
-            ),
-            (_, _) => {
-                panic!("Not supported mirroring type {:?}", self.mirroring);
+            | (ScreenMirroring::Horizontal, 0x2C00) => (0x400, 0),
+            // Four-screen carts have all four nametables, the second one is
+            // the neighbour to the right
+            (_, address) => {
+                let first = (address - 0x2000) as usize;
+
+                (first, first ^ 0x400)
             }
-        };
+        }
+    }
+
+    // The colour number (0-3) of pattern table pixel `x`, `y` of `tile`
+    fn pattern_pixel(&self, bank: u16, tile: u8, x: usize, y: usize) -> u8 {
+        let address = (bank + tile as u16 * 16) as usize + y;
+        let high = self.chr_rom[address] >> (7 - x) & 1;
+        let low = self.chr_rom[address + 8] >> (7 - x) & 1;
+
+        low << 1 | high
+    }
+
+    fn render_background(&mut self, background: &mut [Option<u8>]) {
+        let scroll_x = self.ppu_registers.scroll.scroll_x as usize;
+        let scroll_y = self.ppu_registers.scroll.scroll_y as usize;
+
+        let (first, second) = self.name_table_offsets();
+
+        // Copied so the nametables can be read while drawing into the frame
+        let vram = self.ppu_vram;
+        let first_nametable = &vram[first..first + 0x400];
+        let second_nametable = &vram[second..second + 0x400];
 
         self.render_name_table(
+            background,
             first_nametable,
             ViewPortRect::new(scroll_x, scroll_y, 256, 240),
             -(scroll_x as isize),
@@ -288,6 +378,7 @@ impl NES {
 
         if scroll_x > 0 {
             self.render_name_table(
+                background,
                 second_nametable,
                 ViewPortRect::new(0, 0, scroll_x, 240),
                 (256 - scroll_x) as isize,
@@ -295,6 +386,7 @@ impl NES {
             );
         } else if scroll_y > 0 {
             self.render_name_table(
+                background,
                 second_nametable,
                 ViewPortRect::new(0, 0, 256, scroll_y),
                 0,
@@ -303,8 +395,11 @@ impl NES {
         }
     }
 
+    // Writes the opaque pixels of a name table into `background`, pixels using
+    // colour 0 of their palette are left as `None` so sprites can show through
     fn render_name_table(
         &mut self,
+        background: &mut [Option<u8>],
         name_table: &[u8],
         view_port: ViewPortRect,
         shift_x: isize,
@@ -316,10 +411,10 @@ impl NES {
             .background_pattern_address_value();
         let attribute_table = &name_table[0x3C0..0x400];
 
-        for i in 0..0x3C0 {
+        for (i, tile) in name_table.iter().take(0x3C0).enumerate() {
             let tile_x = i % 32;
             let tile_y = i / 32;
-            let tile = name_table[i] as u16;
+            let tile = *tile as u16;
             let tile =
                 &self.chr_rom[(bank + tile * 16) as usize..=(bank + tile * 16 + 15) as usize];
             let palette = self.background_palette(attribute_table, tile_x, tile_y);
@@ -331,34 +426,41 @@ impl NES {
                 for x in (0..=7).rev() {
                     let value = (1 & low) << 1 | 1 & high;
 
-                    high = high >> 1;
-                    low = low >> 1;
-
-                    let color = palette::SYSTEM_PALLETE[palette[value as usize] as usize];
+                    high >>= 1;
+                    low >>= 1;
 
                     let pixel_x = tile_x * 8 + x;
                     let pixel_y = tile_y * 8 + y;
 
-                    if view_port.point_is_bounded(pixel_x, pixel_y) {
-                        self.current_frame.set_pixel(
-                            (shift_x + pixel_x as isize) as usize,
-                            (shift_y + pixel_y as isize) as usize,
-                            color,
-                        );
+                    if value == 0 || !view_port.point_is_bounded(pixel_x, pixel_y) {
+                        continue;
+                    }
+
+                    let screen_x = (shift_x + pixel_x as isize) as usize;
+                    let screen_y = (shift_y + pixel_y as isize) as usize;
+
+                    if screen_x < FRAME_WIDTH && screen_y < FRAME_HEIGHT {
+                        background[screen_y * FRAME_WIDTH + screen_x] =
+                            Some(palette[value as usize]);
                     }
                 }
             }
         }
     }
 
-    fn render_sprites(&mut self) {
-        for i in (0..self.oam_data.len()).step_by(4).rev() {
+    // Sprites are evaluated from OAM index 0 upwards and the first opaque pixel
+    // claims its position, so a lower-index sprite wins even when it is behind
+    // the background and a higher-index sprite underneath it is in front.
+    fn render_sprites(&mut self, sprites: &mut [Option<SpritePixel>]) {
+        for i in (0..self.oam_data.len()).step_by(4) {
             let tile = self.oam_data[i + 1] as u16;
             let tile_x = self.oam_data[i + 3] as usize;
             let tile_y = self.oam_data[i] as usize;
 
-            let flip_vertical = self.oam_data[i + 2] >> 7 & 1 == 1;
-            let flip_horizontal = self.oam_data[i + 2] >> 6 & 1 == 1;
+            let attributes = self.oam_data[i + 2];
+            let flip_vertical = attributes >> 7 & 1 == 1;
+            let flip_horizontal = attributes >> 6 & 1 == 1;
+            let behind_background = attributes >> 5 & 1 == 1;
 
             let palette = self.sprite_palette(i);
 
@@ -371,34 +473,30 @@ impl NES {
                 let mut high = tile[y];
                 let mut low = tile[y + 8];
 
-                'inner: for x in (0..=7).rev() {
+                for x in (0..=7).rev() {
                     let value = (1 & low) << 1 | 1 & high;
 
-                    high = high >> 1;
-                    low = low >> 1;
+                    high >>= 1;
+                    low >>= 1;
 
                     if value == 0 {
-                        continue 'inner;
+                        continue;
                     }
 
-                    let color = palette::SYSTEM_PALLETE[palette[value as usize] as usize];
+                    let pixel_x = tile_x + if flip_horizontal { 7 - x } else { x };
+                    let pixel_y = tile_y + if flip_vertical { 7 - y } else { y };
 
-                    match (flip_horizontal, flip_vertical) {
-                        (false, false) => {
-                            self.current_frame.set_pixel(tile_x + x, tile_y + y, color)
-                        }
-                        (true, false) => {
-                            self.current_frame
-                                .set_pixel(tile_x + 7 - x, tile_y + y, color)
-                        }
-                        (false, true) => {
-                            self.current_frame
-                                .set_pixel(tile_x + x, tile_y + 7 - y, color)
-                        }
-                        (true, true) => {
-                            self.current_frame
-                                .set_pixel(tile_x + 7 - x, tile_y + 7 - y, color)
-                        }
+                    if pixel_x >= FRAME_WIDTH || pixel_y >= FRAME_HEIGHT {
+                        continue;
+                    }
+
+                    let pixel = &mut sprites[pixel_y * FRAME_WIDTH + pixel_x];
+
+                    if pixel.is_none() {
+                        *pixel = Some(SpritePixel {
+                            color: palette[value as usize],
+                            behind_background,
+                        });
                     }
                 }
             }
@@ -406,6 +504,252 @@ impl NES {
     }
 }
 
-fn page_crossed(old_addr: u16, new_addr: u16) -> bool {
-    old_addr & 0xFF00 != new_addr & 0xFF00
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::cpu::Bus;
+
+    #[allow(dead_code)]
+    fn solid_tile_nes() -> NES {
+        // tile 1 uses colour 1 for every pixel
+        let mut nes = NES {
+            chr_rom: vec![0; 0x2000],
+            ..Default::default()
+        };
+        nes.chr_rom[16..24].copy_from_slice(&[0xFF; 8]);
+
+        nes.palette_table[0x00] = 0x0F;
+        nes.palette_table[0x01] = 0x11;
+        nes.palette_table[0x11] = 0x21;
+        nes.palette_table[0x15] = 0x31;
+
+        nes.ppu_registers.mask.update(0b0001_1110);
+
+        nes
+    }
+
+    #[allow(dead_code)]
+    fn pixel(nes: &NES, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = (y * FRAME_WIDTH + x) * 3;
+        let data = nes.current_frame.data();
+
+        (data[base], data[base + 1], data[base + 2])
+    }
+
+    #[test]
+    fn lower_index_sprite_behind_background_wins() {
+        let mut nes = solid_tile_nes();
+
+        nes.ppu_vram[2 * 32 + 2] = 1;
+        nes.oam_data[0..4].copy_from_slice(&[16, 1, 0b0010_0000, 16]);
+        nes.oam_data[4..8].copy_from_slice(&[16, 1, 0b0000_0001, 16]);
+
+        nes.render();
+
+        assert_eq!(pixel(&nes, 16, 16), palette::SYSTEM_PALLETE[0x11]);
+    }
+
+    #[test]
+    fn sprite_behind_background_shows_through_transparent_background() {
+        let mut nes = solid_tile_nes();
+
+        nes.oam_data[0..4].copy_from_slice(&[16, 1, 0b0010_0000, 16]);
+
+        nes.render();
+
+        assert_eq!(pixel(&nes, 16, 16), palette::SYSTEM_PALLETE[0x21]);
+    }
+
+    #[test]
+    fn leftmost_column_is_clipped() {
+        let mut nes = solid_tile_nes();
+
+        nes.ppu_vram[0] = 1;
+        nes.oam_data[0..4].copy_from_slice(&[16, 1, 0, 4]);
+        nes.ppu_registers.mask.update(0b0001_1000);
+
+        nes.render();
+
+        assert_eq!(pixel(&nes, 0, 0), palette::SYSTEM_PALLETE[0x0F]);
+        assert_eq!(pixel(&nes, 4, 16), palette::SYSTEM_PALLETE[0x0F]);
+        assert_eq!(pixel(&nes, 7, 0), palette::SYSTEM_PALLETE[0x0F]);
+        assert_eq!(pixel(&nes, 8, 16), palette::SYSTEM_PALLETE[0x21]);
+    }
+
+    #[test]
+    fn disabled_background_renders_backdrop() {
+        let mut nes = solid_tile_nes();
+
+        nes.ppu_vram[0] = 1;
+        nes.ppu_registers.mask.update(0b0001_0110);
+
+        nes.render();
+
+        assert_eq!(pixel(&nes, 8, 0), palette::SYSTEM_PALLETE[0x0F]);
+    }
+
+    #[test]
+    fn greyscale_masks_palette_index() {
+        let mut nes = solid_tile_nes();
+
+        nes.ppu_vram[1] = 1;
+        nes.ppu_registers.mask.update(0b0001_1111);
+
+        nes.render();
+
+        assert_eq!(pixel(&nes, 8, 0), palette::SYSTEM_PALLETE[0x10]);
+    }
+
+    #[test]
+    fn emphasis_dims_other_channels() {
+        let mut nes = solid_tile_nes();
+
+        nes.palette_table[0x01] = 0x30;
+        nes.ppu_vram[1] = 1;
+        nes.ppu_registers.mask.update(0b0011_1110);
+
+        nes.render();
+
+        let (red, green, blue) = pixel(&nes, 8, 0);
+
+        assert_eq!(red, 0xFF);
+        assert!(green < 0xFF);
+        assert!(blue < 0xFF);
+    }
+
+    // NMI handler at $9000, IRQ and BRK handler at $A000
+    #[allow(dead_code)]
+    fn interrupt_nes(program: &[u8]) -> NES {
+        let mut prg_rom = vec![0xEA; 0x8000];
+        prg_rom[..program.len()].copy_from_slice(program);
+
+        let cart = cartridge::test::RomBuilder::new()
+            .prg_rom(prg_rom)
+            .nmi_vector(0x9000)
+            .reset_vector(0x8000)
+            .irq_vector(0xA000)
+            .cartridge();
+
+        NES::from_cartridge(cart)
+    }
+
+    #[test]
+    fn brk_pushes_break_flag_and_uses_irq_vector() {
+        let mut nes = interrupt_nes(&[0x00, 0xFF]);
+
+        assert_eq!(nes.step_instruction(), 7);
+        assert_eq!(nes.cpu.registers.program_counter, 0xA000);
+        assert_eq!(nes.cpu_ram[0x1FD], 0x80);
+        assert_eq!(nes.cpu_ram[0x1FC], 0x02);
+        assert_eq!(nes.cpu_ram[0x1FB], 0x34);
+        assert!(nes.cpu.registers.status.interrupt_disable());
+    }
+
+    #[test]
+    fn irq_waits_for_interrupt_disable_to_clear() {
+        // NOP ; CLI ; NOP
+        let mut nes = interrupt_nes(&[0xEA, 0x58, 0xEA]);
+        nes.set_irq(IrqSource::Mapper, true);
+
+        nes.step_instruction();
+        assert_eq!(nes.cpu.registers.program_counter, 0x8001);
+
+        // the I flag changes on CLI's last cycle, so the IRQ follows the next instruction
+        nes.step_instruction();
+        assert_eq!(nes.cpu.registers.program_counter, 0x8002);
+
+        assert_eq!(nes.step_instruction(), 2 + 7);
+        assert_eq!(nes.cpu.registers.program_counter, 0xA000);
+        assert_eq!(nes.cpu_ram[0x1FB] & 0b0011_0000, 0b0010_0000);
+    }
+
+    #[test]
+    fn irq_enabled_by_rti_is_taken_before_the_next_opcode() {
+        // RTI to $8050 with I clear in the pulled status
+        let mut nes = interrupt_nes(&[0x40]);
+        nes.cpu_ram[0x1FE] = 0b0010_0000;
+        nes.cpu_ram[0x1FF] = 0x50;
+        nes.cpu_ram[0x100] = 0x80;
+        nes.set_irq(IrqSource::Mapper, true);
+
+        assert!(nes.cpu.registers.status.interrupt_disable());
+        assert_eq!(nes.step_instruction(), 6 + 7);
+        assert_eq!(nes.cpu.registers.program_counter, 0xA000);
+        assert_eq!(nes.cpu_ram[0x100], 0x80);
+        assert_eq!(nes.cpu_ram[0x1FF], 0x50);
+    }
+
+    #[test]
+    fn irq_line_is_held_by_any_source() {
+        let mut nes = NES::default();
+
+        nes.set_irq(IrqSource::Mapper, true);
+        nes.set_irq(IrqSource::External, true);
+        nes.set_irq(IrqSource::Mapper, false);
+
+        assert!(nes.irq_asserted());
+
+        nes.set_irq(IrqSource::External, false);
+
+        assert!(!nes.irq_asserted());
+    }
+
+    #[test]
+    fn nmi_hijacks_brk() {
+        let mut nes = interrupt_nes(&[0x00, 0xFF]);
+        nes.nmi_pending = true;
+
+        nes.cpu_clock();
+
+        assert_eq!(nes.cpu.registers.program_counter, 0x9000);
+        assert_eq!(nes.cpu_ram[0x1FB] & 0b0001_0000, 0b0001_0000);
+    }
+
+    #[test]
+    fn enabling_nmi_during_vblank_waits_an_instruction() {
+        // LDA #$80 ; STA $2000 ; NOP
+        let mut nes = interrupt_nes(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0xEA]);
+        nes.ppu_warmed_up = true;
+        nes.ppu_registers.status.set_vblank_started(true);
+
+        nes.step_instruction();
+        nes.step_instruction();
+        assert_eq!(nes.cpu.registers.program_counter, 0x8005);
+
+        nes.step_instruction();
+        assert_eq!(nes.cpu.registers.program_counter, 0x9000);
+        assert_eq!(nes.cpu_ram[0x1FC], 0x06);
+    }
+
+    #[test]
+    fn from_cartridge_starts_at_the_reset_vector() {
+        let nes = interrupt_nes(&[]);
+
+        assert_eq!(nes.cpu.registers.program_counter, 0x8000);
+        assert_eq!(nes.clock_count, 7);
+    }
+
+    #[test]
+    fn run_frame_stops_after_one_frame() {
+        let mut nes = interrupt_nes(&[]);
+
+        nes.run_frame();
+
+        assert_eq!(nes.ppu_frame_count, 1);
+
+        nes.run_frame();
+
+        assert_eq!(nes.ppu_frame_count, 2);
+    }
+
+    #[test]
+    fn instances_run_independently() {
+        let mut first = interrupt_nes(&[]);
+        let second = interrupt_nes(&[]);
+
+        assert_eq!(first.step_instruction(), 2);
+        assert_eq!(first.cpu.registers.program_counter, 0x8001);
+        assert_eq!(second.cpu.registers.program_counter, 0x8000);
+    }
 }
@@ -1,39 +1,50 @@
 use colored::Colorize;
 
 use crate::{
-    cpu::{self, AddrMode, CPU},
+    cpu::{instructions::Instructions, AddrMode, Bus},
     NES,
 };
 
 const ILLEGAL_NOPS: [&'static str; 2] = ["DOP", "TOP"];
 
-pub fn log(nes: &mut NES) {
+pub fn log(nes: &NES) {
     println!(
         "{: <6}{: <10}{: <32}{}",
-        program_counter_log(nes.cpu_registers.program_counter).blue(),
+        program_counter_log(nes.cpu.registers.program_counter).blue(),
         instruction_log(nes).cyan(),
         assembly_log(nes).yellow(),
         cpu_registers_log(nes).magenta()
     );
 }
 
+// The same line as log without the colours, in the format of nestest.log
+pub fn trace(nes: &NES) -> String {
+    format!(
+        "{: <6}{: <10}{: <32}{}",
+        program_counter_log(nes.cpu.registers.program_counter),
+        instruction_log(nes),
+        assembly_log(nes),
+        cpu_registers_log(nes)
+    )
+}
+
 fn program_counter_log(program_counter: u16) -> String {
     format!("{:04X}", program_counter)
 }
 
-fn instruction_log(nes: &mut NES) -> String {
-    let opcode = nes.cpu_read(nes.cpu_registers.program_counter);
-    let instruction = &cpu::instructions::INSTRUCTIONS_TABLE[opcode as usize];
+fn instruction_log(nes: &NES) -> String {
+    let opcode = nes.peek(nes.cpu.registers.program_counter);
+    let instruction = &Instructions::<NES>::TABLE[opcode as usize];
 
     let log = match instruction.len {
         1 => format!("{:02X}", opcode),
         2 => {
-            let operand = nes.cpu_read(nes.cpu_registers.program_counter + 1);
+            let operand = nes.peek(nes.cpu.registers.program_counter + 1);
             format!("{:02X} {:02X}", opcode, operand)
         }
         3 => {
-            let operand1 = nes.cpu_read(nes.cpu_registers.program_counter + 1);
-            let operand2 = nes.cpu_read(nes.cpu_registers.program_counter + 2);
+            let operand1 = nes.peek(nes.cpu.registers.program_counter + 1);
+            let operand2 = nes.peek(nes.cpu.registers.program_counter + 2);
             format!("{:02X} {:02X} {:02X}", opcode, operand1, operand2)
         }
         _ => unreachable!(),
@@ -46,9 +57,9 @@ fn instruction_log(nes: &mut NES) -> String {
     }
 }
 
-fn assembly_log(nes: &mut NES) -> String {
-    let opcode = nes.cpu_read(nes.cpu_registers.program_counter);
-    let instruction = &cpu::instructions::INSTRUCTIONS_TABLE[opcode as usize];
+fn assembly_log(nes: &NES) -> String {
+    let opcode = nes.peek(nes.cpu.registers.program_counter);
+    let instruction = &Instructions::<NES>::TABLE[opcode as usize];
 
     let instruction_name = if ILLEGAL_NOPS.contains(&instruction.name) {
         "NOP"
@@ -56,19 +67,19 @@ fn assembly_log(nes: &mut NES) -> String {
         instruction.name
     };
 
-    let program_counter = nes.cpu_registers.program_counter;
+    let program_counter = nes.cpu.registers.program_counter;
     let (mem_addr, stored) = match instruction.mode {
         AddrMode::Immediate | AddrMode::Accumulator | AddrMode::Implied => (0, 0),
         _ => {
-            let (addr, _) = nes.get_absolute_address(program_counter + 1, &instruction.mode);
-            let stored = nes.cpu_read(addr);
+            let addr = peek_absolute_address(nes, program_counter + 1, &instruction.mode);
+            let stored = nes.peek(addr);
 
             (addr, stored)
         }
     };
 
-    let addr = nes.cpu_read(program_counter + 1);
-    let addr_16 = nes.cpu_read_u16(program_counter + 1);
+    let addr = nes.peek(program_counter + 1);
+    let addr_16 = nes.peek_u16(program_counter + 1);
 
     match instruction.mode {
         AddrMode::Accumulator => format!("{} A", instruction_name),
@@ -93,12 +104,12 @@ fn assembly_log(nes: &mut NES) -> String {
         }
         AddrMode::Indirect => {
             let jump_addr = if addr_16 & 0x00FF == 0x00FF {
-                let low = nes.cpu_read(addr_16);
-                let high = nes.cpu_read(addr_16 & 0xFF00);
+                let low = nes.peek(addr_16);
+                let high = nes.peek(addr_16 & 0xFF00);
 
                 u16::from_le_bytes([low, high])
             } else {
-                nes.cpu_read_u16(addr_16)
+                nes.peek_u16(addr_16)
             };
 
             format!(
@@ -135,7 +146,7 @@ fn assembly_log(nes: &mut NES) -> String {
                 "{} (${:02X},X) @ {:02X} = {:04X} = {:02X}",
                 instruction_name,
                 addr,
-                addr.wrapping_add(nes.cpu_registers.x),
+                addr.wrapping_add(nes.cpu.registers.x),
                 mem_addr,
                 stored
             )
@@ -145,7 +156,7 @@ fn assembly_log(nes: &mut NES) -> String {
                 "{} (${:02X}),Y = {:04X} @ {:04X} = {:02X}",
                 instruction_name,
                 addr,
-                mem_addr.wrapping_sub(nes.cpu_registers.y as u16),
+                mem_addr.wrapping_sub(nes.cpu.registers.y as u16),
                 mem_addr,
                 stored
             )
@@ -153,14 +164,53 @@ fn assembly_log(nes: &mut NES) -> String {
     }
 }
 
+// Works out the address an instruction will operate on without touching the bus
+fn peek_absolute_address(nes: &NES, addr: u16, mode: &AddrMode) -> u16 {
+    match mode {
+        AddrMode::ZeroPage => nes.peek(addr) as u16,
+        AddrMode::ZeroPageX => nes.peek(addr).wrapping_add(nes.cpu.registers.x) as u16,
+        AddrMode::ZeroPageY => nes.peek(addr).wrapping_add(nes.cpu.registers.y) as u16,
+        AddrMode::Relative => {
+            let offset = nes.peek(addr) as i8;
+
+            addr.wrapping_add(1).wrapping_add(offset as u16)
+        }
+        AddrMode::Absolute => nes.peek_u16(addr),
+        AddrMode::AbsoluteX => nes.peek_u16(addr).wrapping_add(nes.cpu.registers.x as u16),
+        AddrMode::AbsoluteY => nes.peek_u16(addr).wrapping_add(nes.cpu.registers.y as u16),
+        AddrMode::Indirect => {
+            let old_addr = nes.peek_u16(addr);
+            let low = nes.peek(old_addr);
+            let high = nes.peek((old_addr & 0xFF00) | (old_addr.wrapping_add(1) & 0x00FF));
+
+            u16::from_le_bytes([low, high])
+        }
+        AddrMode::IndirectX => {
+            let pointer = nes.peek(addr).wrapping_add(nes.cpu.registers.x);
+            let low = nes.peek(pointer as u16);
+            let high = nes.peek(pointer.wrapping_add(1) as u16);
+
+            u16::from_le_bytes([low, high])
+        }
+        AddrMode::IndirectY => {
+            let zero_page_addr = nes.peek(addr);
+            let low = nes.peek(zero_page_addr as u16);
+            let high = nes.peek(zero_page_addr.wrapping_add(1) as u16);
+
+            u16::from_le_bytes([low, high]).wrapping_add(nes.cpu.registers.y as u16)
+        }
+        _ => unreachable!("{:?} has no operating address", mode),
+    }
+}
+
 fn cpu_registers_log(nes: &NES) -> String {
     format!(
         "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
-        nes.cpu_registers.accumulator,
-        nes.cpu_registers.x,
-        nes.cpu_registers.y,
-        nes.cpu_registers.status.bits(),
-        nes.cpu_registers.stack_pointer,
+        nes.cpu.registers.accumulator,
+        nes.cpu.registers.x,
+        nes.cpu.registers.y,
+        nes.cpu.registers.status.bits(),
+        nes.cpu.registers.stack_pointer,
         nes.ppu_scanline,
         nes.ppu_cycles,
         nes.clock_count,
@@ -10,8 +10,7 @@ use sdl2::{
     EventPump,
 };
 
-const TARGET_FPS: u64 = 60;
-const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / TARGET_FPS);
+const ROM_FILE: &str = "roms/pacman.nes";
 
 fn main() {
     let (creator, mut canvas, mut event_pump) = init_sdl2();
@@ -19,22 +18,40 @@ fn main() {
         .create_texture_target(PixelFormatEnum::RGB24, 256, 240)
         .unwrap();
 
-    let mut nes = NES::default();
+    let cart = match cartridge::Cartridge::load(ROM_FILE) {
+        Ok(cart) => cart,
+        Err(err) => {
+            eprintln!("unable to load {}: {}", ROM_FILE, err);
+            return;
+        }
+    };
+    let frame_duration = Duration::from_secs_f64(1.0 / cart.region.frames_per_second());
+
+    let mut nes = NES::from_cartridge(cart);
+    nes.set_diagnostics_enabled(true);
 
-    nes.start("roms/pacman.nes", move |frame, controller| {
+    'running: loop {
         let frame_start = Instant::now();
 
-        texture.update(None, frame.data(), 256 * 3).unwrap();
+        nes.run_frame();
+
+        for diagnostic in nes.take_diagnostics() {
+            eprintln!("{}", diagnostic);
+        }
+
+        texture.update(None, nes.frame().data(), 256 * 3).unwrap();
         canvas.copy(&texture, None, None).unwrap();
         canvas.present();
 
+        let controller = nes.controller();
+
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => std::process::exit(0),
+                } => break 'running,
                 Event::KeyDown { keycode, .. } => match keycode {
                     Some(Keycode::Down) => controller.button_state.set_down(true),
                     Some(Keycode::Up) => controller.button_state.set_up(true),
@@ -62,10 +79,10 @@ fn main() {
         }
 
         let frame_time = frame_start.elapsed();
-        if frame_time < FRAME_DURATION {
-            std::thread::sleep(FRAME_DURATION - frame_time);
+        if frame_time < frame_duration {
+            std::thread::sleep(frame_duration - frame_time);
         }
-    });
+    }
 }
 
 fn init_sdl2() -> (TextureCreator<WindowContext>, Canvas<Window>, EventPump) {
@@ -81,5 +98,5 @@ fn init_sdl2() -> (TextureCreator<WindowContext>, Canvas<Window>, EventPump) {
     let event_pump = sdl_context.event_pump().unwrap();
     canvas.set_scale(3.0, 3.0).unwrap();
 
-    return (canvas.texture_creator(), canvas, event_pump);
+    (canvas.texture_creator(), canvas, event_pump)
 }
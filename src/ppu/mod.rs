@@ -1,9 +1,11 @@
-use crate::{cartridge::ScreenMirroring, NES};
+use crate::{cartridge::ScreenMirroring, diagnostics::DiagnosticKind, NES};
 
 pub(crate) mod registers;
 
 pub trait PPU {
-    fn ppu_clock(&mut self, cycles: usize) -> bool;
+    fn ppu_tick(&mut self) -> bool;
+    fn ppu_rendering_enabled(&self) -> bool;
+    fn ppu_nmi_output(&self) -> bool;
     fn ppu_read(&mut self) -> u8;
     fn ppu_write(&mut self, value: u8);
 
@@ -11,52 +13,93 @@ pub trait PPU {
     fn ppu_write_control(&mut self, data: u8);
     fn ppu_write_oam_address(&mut self, data: u8);
     fn ppu_write_oam_data(&mut self, data: u8);
-    fn ppu_write_oam_dma(&mut self, buffer: &[u8; 256]);
     fn ppu_write_mask(&mut self, data: u8);
     fn ppu_write_scroll(&mut self, data: u8);
 
     fn ppu_read_status(&mut self) -> u8;
     fn ppu_read_oam_data(&mut self) -> u8;
 
+    fn ppu_open_bus(&mut self) -> u8;
+    fn ppu_refresh_open_bus(&mut self, data: u8, mask: u8);
+
     fn background_palette(&self, attribute_table: &[u8], tile_x: usize, tile_y: usize) -> [u8; 4];
     fn sprite_palette(&self, index: usize) -> [u8; 4];
     fn mirror_vram_address(&self, address: u16) -> u16;
+    fn mirror_palette_address(&self, address: u16) -> usize;
 
     fn is_sprite_0_hit(&self, cycle: usize) -> bool;
 }
 
+const DOTS_PER_SCANLINE: usize = 341;
+
+// Bits of the I/O latch fade to 0 after roughly 600ms without being refreshed
+const OPEN_BUS_DECAY_FRAMES: usize = 36;
+
 impl PPU for NES {
-    fn ppu_clock(&mut self, cycles: usize) -> bool {
-        self.ppu_cycles += cycles * 3;
+    fn ppu_tick(&mut self) -> bool {
+        self.ppu_cycles += 1;
 
-        if self.ppu_cycles >= 341 {
-            if self.is_sprite_0_hit(self.ppu_cycles) {
-                self.ppu_registers.status.set_sprite_zero_hit(true);
-            }
+        let pre_render_scanline = self.region.pre_render_scanline();
+
+        // The pre-render scanline is one dot shorter on odd frames while rendering
+        let skip_dot = self.ppu_scanline == pre_render_scanline
+            && self.ppu_cycles == DOTS_PER_SCANLINE - 1
+            && self.ppu_odd_frame
+            && self.region.skips_odd_frame_dot()
+            && self.ppu_rendering_enabled();
 
-            self.ppu_cycles = self.ppu_cycles - 341;
+        if self.ppu_cycles >= DOTS_PER_SCANLINE || skip_dot {
+            self.ppu_cycles = 0;
             self.ppu_scanline += 1;
 
-            if self.ppu_scanline == 241 {
-                self.ppu_registers.status.set_vblank_started(true);
-                self.ppu_registers.status.set_sprite_zero_hit(true);
+            if self.ppu_scanline > pre_render_scanline {
+                self.ppu_scanline = 0;
+                self.ppu_odd_frame = !self.ppu_odd_frame;
+                self.ppu_frame_count += 1;
 
-                if self.ppu_registers.control.generate_nmi() {
-                    self.next_interrupt = Some(crate::Interrupt::NMI)
-                }
+                return true;
             }
 
-            if self.ppu_scanline >= 262 {
-                self.ppu_scanline = 0;
-                self.next_interrupt = None;
-                self.ppu_registers.status.set_sprite_zero_hit(false);
-                self.ppu_registers.status.set_vblank_started(false);
+            return false;
+        }
 
-                return true;
+        if self.ppu_scanline < 240
+            && !self.ppu_registers.status.sprite_zero_hit()
+            && self.is_sprite_0_hit(self.ppu_cycles)
+        {
+            self.ppu_registers.status.set_sprite_zero_hit(true);
+        }
+
+        if self.ppu_cycles == 1 {
+            match self.ppu_scanline {
+                scanline if scanline == self.region.vblank_scanline() => {
+                    // A $2002 read on the previous dot suppresses the flag for this frame
+                    if !self.ppu_vblank_suppressed {
+                        self.ppu_registers.status.set_vblank_started(true);
+                    }
+
+                    self.ppu_vblank_suppressed = false;
+                }
+                scanline if scanline == pre_render_scanline => {
+                    self.ppu_registers.status.set_sprite_zero_hit(false);
+                    self.ppu_registers.status.set_sprite_overflow(false);
+                    self.ppu_registers.status.set_vblank_started(false);
+
+                    self.ppu_warmed_up = true;
+                }
+                _ => {}
             }
         }
 
-        return false;
+        false
+    }
+
+    fn ppu_rendering_enabled(&self) -> bool {
+        self.ppu_registers.mask.show_background() || self.ppu_registers.mask.show_sprite()
+    }
+
+    fn ppu_nmi_output(&self) -> bool {
+        self.ppu_registers.status.vblank_started() && self.ppu_registers.control.generate_nmi()
     }
 
     fn ppu_read(&mut self) -> u8 {
@@ -64,74 +107,93 @@ impl PPU for NES {
 
         self.ppu_registers.increment_vram_address();
 
-        match address {
+        let result = match address {
             0..=0x1FFF => {
                 let result = self.ppu_read_buffer;
-                self.ppu_read_buffer = self.chr_rom[address as usize];
+                self.ppu_read_buffer = self.chr_rom.get(address as usize).copied().unwrap_or(0);
                 result
             }
-            0x2000..=0x2FFF => {
+            0x2000..=0x3EFF => {
                 let result = self.ppu_read_buffer;
                 self.ppu_read_buffer = self.ppu_vram[self.mirror_vram_address(address) as usize];
                 result
             }
-            0x3000..=0x3EFF => {
-                unreachable!(
-                    "0x3000..0x3EFF shouldnt be used, attempted to use {}",
-                    address
-                )
-            }
-            0x3F10 | 0x3F14 | 0x3F18 | 0x3F1C => {
-                let add_mirror = address - 0x10;
-                self.palette_table[(add_mirror - 0x3f00) as usize]
+            0x3F00..=0x3FFF => {
+                // Palette reads bypass the buffer, which is filled from the nametable underneath
+                self.ppu_read_buffer = self.ppu_vram[self.mirror_vram_address(address) as usize];
+
+                let mut value = self.palette_table[self.mirror_palette_address(address)];
+
+                if self.ppu_registers.mask.greyscale() {
+                    value &= 0x30;
+                }
+
+                self.ppu_refresh_open_bus(value, 0x3F);
+
+                return self.ppu_open_bus();
             }
-            0x3F00..=0x3FFF => self.palette_table[(address - 0x3F00) as usize],
-            _ => unreachable!("attempted to access mirrored address space {}", address),
-        }
+            _ => unreachable!("PPU address {:04X} is past 14 bits", address),
+        };
+
+        self.ppu_refresh_open_bus(result, 0xFF);
+
+        result
     }
 
     fn ppu_write(&mut self, value: u8) {
         let address = self.ppu_registers.address.as_u16();
         match address {
-            0..=0x1fff => println!("attempt to write to chr rom space {}", address),
-            0x2000..=0x2fff => {
-                self.ppu_vram[self.mirror_vram_address(address) as usize] = value;
+            0..=0x1fff => {
+                if self.chr_ram {
+                    self.chr_rom[address as usize] = value;
+                } else {
+                    self.report(DiagnosticKind::ChrRomWrite, address, value);
+                }
             }
-            0x3000..=0x3eff => unimplemented!("address {} shouldn't be used in reallity", address),
-            0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => {
-                let add_mirror = address - 0x10;
-                self.palette_table[(add_mirror - 0x3f00) as usize] = value;
+            // $3000-$3EFF mirrors the nametables at $2000-$2EFF
+            0x2000..=0x3eff => {
+                self.ppu_vram[self.mirror_vram_address(address) as usize] = value;
             }
             0x3f00..=0x3fff => {
-                self.palette_table[(address - 0x3f00) as usize] = value;
+                self.palette_table[self.mirror_palette_address(address)] = value;
             }
-            _ => panic!("unexpected access to mirrored space {}", address),
+            _ => unreachable!("PPU address {:04X} is past 14 bits", address),
         }
 
         self.ppu_registers.increment_vram_address();
     }
 
     fn ppu_write_address(&mut self, data: u8) {
+        if !self.ppu_warmed_up {
+            return;
+        }
+
         self.ppu_registers.address.update(data);
     }
 
     fn ppu_write_control(&mut self, data: u8) {
-        let nmi_status_before = self.ppu_registers.control.generate_nmi();
+        if !self.ppu_warmed_up {
+            return;
+        }
 
+        // Enabling NMI during vblank raises the NMI line, the CPU only sees the
+        // edge on its next cycle so the NMI follows the next instruction
         self.ppu_registers.control.update(data);
-
-        let nmi_status_after = self.ppu_registers.control.generate_nmi();
-
-        if !nmi_status_before && nmi_status_after && self.ppu_registers.status.vblank_started() {
-            self.next_interrupt = Some(crate::Interrupt::NMI)
-        }
     }
 
     fn ppu_write_mask(&mut self, data: u8) {
+        if !self.ppu_warmed_up {
+            return;
+        }
+
         self.ppu_registers.mask.update(data);
     }
 
     fn ppu_write_scroll(&mut self, data: u8) {
+        if !self.ppu_warmed_up {
+            return;
+        }
+
         self.ppu_registers.scroll.update(data)
     }
 
@@ -144,15 +206,26 @@ impl PPU for NES {
         self.ppu_registers.oam_addr = self.ppu_registers.oam_addr.wrapping_add(1);
     }
 
-    fn ppu_write_oam_dma(&mut self, buffer: &[u8; 256]) {
-        for data in buffer.iter() {
-            self.ppu_write_oam_data(*data);
+    fn ppu_read_status(&mut self) -> u8 {
+        // Reading just before the flag is set suppresses it for this frame, and
+        // reading within two dots of it being set cancels the NMI. Any later and
+        // the CPU has already seen the edge.
+        let vblank_dot = self.ppu_scanline == self.region.vblank_scanline();
+
+        if vblank_dot && self.ppu_cycles == 0 {
+            self.ppu_vblank_suppressed = true;
+        }
+
+        if !(vblank_dot && (1..=2).contains(&self.ppu_cycles)) {
+            self.detect_nmi_edge();
         }
-    }
 
-    fn ppu_read_status(&mut self) -> u8 {
         let status = self.ppu_registers.status.clone();
-        let data = *status.into_bytes().first().unwrap();
+        let status = *status.into_bytes().first().unwrap();
+
+        self.ppu_refresh_open_bus(status, 0xE0);
+
+        let data = self.ppu_open_bus();
 
         self.ppu_registers.status.set_vblank_started(false);
         self.ppu_registers.address.reset_latch();
@@ -162,7 +235,31 @@ impl PPU for NES {
     }
 
     fn ppu_read_oam_data(&mut self) -> u8 {
-        self.oam_data[self.ppu_registers.oam_addr as usize]
+        let data = self.oam_data[self.ppu_registers.oam_addr as usize];
+
+        self.ppu_refresh_open_bus(data, 0xFF);
+
+        data
+    }
+
+    fn ppu_open_bus(&mut self) -> u8 {
+        for bit in 0..8 {
+            if self.ppu_frame_count - self.ppu_open_bus_refreshed[bit] > OPEN_BUS_DECAY_FRAMES {
+                self.ppu_open_bus &= !(1 << bit);
+            }
+        }
+
+        self.ppu_open_bus
+    }
+
+    fn ppu_refresh_open_bus(&mut self, data: u8, mask: u8) {
+        self.ppu_open_bus = (self.ppu_open_bus & !mask) | (data & mask);
+
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 {
+                self.ppu_open_bus_refreshed[bit] = self.ppu_frame_count;
+            }
+        }
     }
 
     fn background_palette(&self, attribute_table: &[u8], tile_x: usize, tile_y: usize) -> [u8; 4] {
@@ -212,10 +309,379 @@ impl PPU for NES {
         }
     }
 
+    fn mirror_palette_address(&self, address: u16) -> usize {
+        let index = (address & 0x1F) as usize;
+
+        match index {
+            0x10 | 0x14 | 0x18 | 0x1C => index - 0x10,
+            _ => index,
+        }
+    }
+
+    // Dot 1 draws pixel 0. The hit needs an opaque sprite 0 pixel over an
+    // opaque background pixel and never happens at x=255 or in a clipped
+    // leftmost column, using the same geometry as the frame renderer
     fn is_sprite_0_hit(&self, cycle: usize) -> bool {
-        let y = self.oam_data[0] as usize;
-        let x = self.oam_data[3] as usize;
+        let mask = &self.ppu_registers.mask;
+
+        if cycle == 0 || cycle > 255 || !mask.show_background() || !mask.show_sprite() {
+            return false;
+        }
+
+        let x = cycle - 1;
+        let y = self.ppu_scanline as usize;
+
+        if x < 8 && !(mask.leftmost_8px_background() && mask.leftmost_8px_sprite()) {
+            return false;
+        }
+
+        let sprite_x = self.oam_data[3] as usize;
+        let sprite_y = self.oam_data[0] as usize;
+
+        if x < sprite_x || x >= sprite_x + 8 || y < sprite_y || y >= sprite_y + 8 {
+            return false;
+        }
+
+        let attributes = self.oam_data[2];
+        let column = if attributes >> 6 & 1 == 1 {
+            7 - (x - sprite_x)
+        } else {
+            x - sprite_x
+        };
+        let row = if attributes >> 7 & 1 == 1 {
+            7 - (y - sprite_y)
+        } else {
+            y - sprite_y
+        };
+        let bank = self.ppu_registers.control.sprite_pattern_address_value();
+
+        if self.pattern_pixel(bank, self.oam_data[1], column, row) == 0 {
+            return false;
+        }
+
+        let scroll_x = self.ppu_registers.scroll.scroll_x as usize;
+        let scroll_y = self.ppu_registers.scroll.scroll_y as usize;
+        let (first, second) = self.name_table_offsets();
+
+        // The renderer leaves the bottom strip empty when both scrolls are set
+        let (name_table, x, y) = match (x + scroll_x, y + scroll_y) {
+            (x, y) if x < 256 && y < 240 => (first, x, y),
+            (x, _) if x >= 256 => (second, x - 256, y),
+            (_, y) if scroll_x == 0 => (second, x, y - 240),
+            _ => return false,
+        };
+
+        let tile = self.ppu_vram[name_table + y / 8 * 32 + x / 8];
+        let bank = self
+            .ppu_registers
+            .control
+            .background_pattern_address_value();
+
+        self.pattern_pixel(bank, tile, x % 8, y % 8) != 0
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::cpu::Bus;
+    #[allow(unused_imports)]
+    use crate::region::Region;
+    #[allow(unused_imports)]
+    use alloc::{vec, vec::Vec};
+
+    #[allow(dead_code)]
+    fn run_until(nes: &mut NES, scanline: usize, dot: usize) {
+        while nes.ppu_scanline != scanline || nes.ppu_cycles != dot {
+            nes.ppu_tick();
+        }
+    }
+
+    #[allow(dead_code)]
+    fn frame_length(nes: &mut NES) -> usize {
+        let mut dots = 1;
+
+        while !nes.ppu_tick() {
+            dots += 1;
+        }
+
+        dots
+    }
+
+    #[allow(dead_code)]
+    fn sprite_zero_nes() -> NES {
+        // tile 1 is solid, sprite 0 uses it at (16, 16)
+        let mut nes = NES {
+            chr_rom: vec![0; 0x2000],
+            ..Default::default()
+        };
+        nes.chr_rom[16..24].copy_from_slice(&[0xFF; 8]);
+        nes.oam_data[..4].copy_from_slice(&[16, 1, 0, 16]);
+        nes.ppu_registers.mask.update(0b0001_1110);
+
+        nes
+    }
+
+    #[test]
+    fn sprite_zero_hit_is_set_on_the_first_overlapping_dot() {
+        let mut nes = sprite_zero_nes();
+        nes.ppu_vram[2 * 32 + 2] = 1;
+
+        run_until(&mut nes, 16, 16);
+
+        assert!(!nes.ppu_registers.status.sprite_zero_hit());
+
+        nes.ppu_tick();
+
+        assert!(nes.ppu_registers.status.sprite_zero_hit());
+    }
+
+    #[test]
+    fn sprite_zero_over_transparent_background_does_not_hit() {
+        let mut nes = sprite_zero_nes();
+
+        run_until(&mut nes, 241, 2);
+
+        assert!(nes.ppu_registers.status.vblank_started());
+        assert!(!nes.ppu_registers.status.sprite_zero_hit());
+    }
+
+    #[test]
+    fn odd_frames_skip_a_dot_while_rendering() {
+        let mut nes = NES::default();
+        nes.ppu_registers.mask.update(0b0000_1000);
+
+        frame_length(&mut nes);
+
+        assert!(nes.ppu_odd_frame);
+        assert_eq!(frame_length(&mut nes), 341 * 262 - 1);
+        assert_eq!(frame_length(&mut nes), 341 * 262);
+    }
+
+    #[test]
+    fn odd_frames_are_full_length_while_not_rendering() {
+        let mut nes = NES::default();
+
+        frame_length(&mut nes);
+
+        assert!(nes.ppu_odd_frame);
+        assert_eq!(frame_length(&mut nes), 341 * 262);
+    }
+
+    #[test]
+    fn pal_frames_are_312_lines_without_a_skipped_dot() {
+        let mut nes = NES::default();
+        nes.set_region(Region::Pal);
+        nes.ppu_registers.mask.update(0b0000_1000);
+
+        frame_length(&mut nes);
+
+        assert!(nes.ppu_odd_frame);
+        assert_eq!(frame_length(&mut nes), 341 * 312);
+    }
+
+    #[test]
+    fn dendy_vblank_starts_late() {
+        let mut nes = NES::default();
+        nes.set_region(Region::Dendy);
+
+        run_until(&mut nes, 241, 2);
+
+        assert!(!nes.ppu_registers.status.vblank_started());
+
+        run_until(&mut nes, 291, 2);
+
+        assert!(nes.ppu_registers.status.vblank_started());
+    }
+
+    #[test]
+    fn status_read_before_vblank_suppresses_flag_and_nmi() {
+        let mut nes = NES::default();
+        nes.ppu_registers.control.update(0b1000_0000);
+
+        run_until(&mut nes, 241, 0);
+
+        assert_eq!(nes.ppu_read_status() & 0x80, 0);
+
+        nes.ppu_tick();
+        nes.detect_nmi_edge();
+
+        assert!(!nes.ppu_registers.status.vblank_started());
+        assert!(!nes.nmi_pending);
+    }
+
+    #[test]
+    fn status_read_on_vblank_dot_suppresses_nmi() {
+        let mut nes = NES::default();
+        nes.ppu_registers.control.update(0b1000_0000);
+
+        run_until(&mut nes, 241, 1);
+
+        assert!(nes.ppu_nmi_output());
+        assert_eq!(nes.ppu_read_status() & 0x80, 0x80);
+
+        nes.detect_nmi_edge();
+
+        assert!(!nes.nmi_pending);
+    }
+
+    #[test]
+    fn status_read_after_vblank_keeps_nmi() {
+        let mut nes = NES::default();
+        nes.ppu_registers.control.update(0b1000_0000);
+
+        run_until(&mut nes, 241, 3);
+
+        assert_eq!(nes.ppu_read_status() & 0x80, 0x80);
+        assert!(nes.nmi_pending);
+    }
+
+    #[test]
+    fn enabling_nmi_during_vblank_raises_the_line() {
+        let mut nes = NES {
+            ppu_warmed_up: true,
+            ..Default::default()
+        };
+
+        run_until(&mut nes, 241, 10);
+        nes.detect_nmi_edge();
+
+        nes.ppu_write_control(0b1000_0000);
+
+        assert!(!nes.nmi_pending);
+
+        nes.detect_nmi_edge();
+
+        assert!(nes.nmi_pending);
+    }
+
+    #[test]
+    fn register_writes_are_ignored_until_warmed_up() {
+        let mut nes = NES::default();
+
+        nes.ppu_write_control(0b1000_0000);
+        nes.ppu_write_mask(0b0001_1000);
+        nes.ppu_write_address(0x21);
+
+        assert!(!nes.ppu_registers.control.generate_nmi());
+        assert!(!nes.ppu_registers.mask.show_background());
+        assert_eq!(nes.ppu_registers.address.as_u16(), 0);
+
+        run_until(&mut nes, 261, 1);
+
+        nes.ppu_write_control(0b1000_0000);
+
+        assert!(nes.ppu_registers.control.generate_nmi());
+    }
+
+    #[test]
+    fn write_only_registers_read_back_the_io_latch() {
+        let mut nes = NES::default();
+
+        nes.cpu_write(0x2000, 0xA5);
+
+        assert_eq!(nes.cpu_read(0x2000), 0xA5);
+        assert_eq!(nes.cpu_read(0x2005), 0xA5);
+        assert_eq!(nes.cpu_read(0x2002) & 0x1F, 0x05);
+    }
+
+    #[test]
+    fn io_latch_decays_without_refresh() {
+        let mut nes = NES::default();
+
+        nes.cpu_write(0x2001, 0xFF);
+
+        for _ in 0..OPEN_BUS_DECAY_FRAMES {
+            frame_length(&mut nes);
+        }
+
+        assert_eq!(nes.cpu_read(0x2003), 0xFF);
+
+        // status reads refresh the top three bits only
+        nes.cpu_read(0x2002);
+        frame_length(&mut nes);
+
+        assert_eq!(nes.cpu_read(0x2003) & 0x1F, 0);
+    }
+
+    #[test]
+    fn palette_reads_fill_buffer_from_nametable() {
+        let mut nes = NES {
+            mirroring: ScreenMirroring::Vertical,
+            ..Default::default()
+        };
+
+        nes.ppu_vram[0x700] = 0x42;
+        nes.palette_table[0] = 0x0F;
+        nes.ppu_registers.address.update(0x3F);
+        nes.ppu_registers.address.update(0x00);
+
+        assert_eq!(nes.ppu_read() & 0x3F, 0x0F);
+        assert_eq!(nes.ppu_read_buffer, 0x42);
+    }
+
+    #[test]
+    fn upper_nametable_mirror_is_readable() {
+        let mut nes = NES::default();
+
+        nes.ppu_vram[0x10] = 0x42;
+        nes.ppu_registers.address.update(0x30);
+        nes.ppu_registers.address.update(0x10);
+
+        nes.ppu_read();
+
+        assert_eq!(nes.ppu_read(), 0x42);
+    }
+
+    #[test]
+    fn chr_ram_is_writable() {
+        let mut nes = NES {
+            chr_ram: true,
+            chr_rom: vec![0; 0x2000],
+            ..Default::default()
+        };
+
+        nes.ppu_registers.address.update(0x01);
+        nes.ppu_registers.address.update(0x23);
+        nes.ppu_write(0x42);
+
+        assert_eq!(nes.chr_rom[0x123], 0x42);
+    }
+
+    #[test]
+    fn upper_nametable_mirror_is_writable() {
+        let mut nes = NES::default();
+
+        nes.ppu_registers.address.update(0x30);
+        nes.ppu_registers.address.update(0x10);
+        nes.ppu_write(0x42);
+
+        assert_eq!(nes.ppu_vram[0x10], 0x42);
+    }
+
+    #[test]
+    fn four_screen_nametables_are_separate() {
+        let mut nes = NES {
+            mirroring: ScreenMirroring::FourScreen,
+            ..Default::default()
+        };
+
+        nes.ppu_registers.address.update(0x2C);
+        nes.ppu_registers.address.update(0x00);
+        nes.ppu_write(0x42);
+
+        assert_eq!(nes.ppu_vram[0xC00], 0x42);
+        assert_eq!(nes.ppu_vram[0x400], 0);
+    }
+
+    #[test]
+    fn address_is_14_bits() {
+        let mut nes = NES::default();
+
+        nes.ppu_registers.address.update(0x7F);
+        nes.ppu_registers.address.update(0x00);
 
-        (y == self.ppu_scanline as usize) && x <= cycle && self.ppu_registers.mask.show_sprite()
+        assert_eq!(nes.ppu_registers.address.as_u16(), 0x3F00);
     }
 }
@@ -56,12 +56,13 @@ impl Address {
         self.low = (data & 0xFF) as u8;
     }
 
+    // The PPU address bus is 14 bits wide
     fn mirror_down(&mut self) {
         if self.as_u16() <= 0x3FFF {
             return;
         }
 
-        self.set(self.as_u16() & 0b01111111_11111111)
+        self.set(self.as_u16() & 0b00111111_11111111)
     }
 
     fn flip_latch(&mut self) {
@@ -241,4 +242,9 @@ impl Mask {
     pub fn update(&mut self, bits: u8) {
         self.bytes = [bits];
     }
+
+    // The BGR emphasis bits, red in bit 0
+    pub fn emphasis(&self) -> u8 {
+        self.bytes[0] >> 5
+    }
 }
@@ -0,0 +1,56 @@
+use crate::scheduler::{NTSC_CPU_DIVIDER, NTSC_PPU_DIVIDER};
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    // Dividers of the master clock, NTSC runs off 21.477272 MHz while PAL and
+    // Dendy share a 26.601712 MHz crystal
+    pub fn cpu_divider(&self) -> u64 {
+        match self {
+            Region::Ntsc => NTSC_CPU_DIVIDER,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    pub fn ppu_divider(&self) -> u64 {
+        match self {
+            Region::Ntsc => NTSC_PPU_DIVIDER,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    // Dendy keeps the PAL frame but puts its extra 50 lines before vblank so
+    // that NTSC games get the same amount of time in vblank
+    pub fn vblank_scanline(&self) -> usize {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    pub fn pre_render_scanline(&self) -> usize {
+        match self {
+            Region::Ntsc => 261,
+            Region::Pal | Region::Dendy => 311,
+        }
+    }
+
+    // Only the NTSC PPU shortens odd frames
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    pub fn frames_per_second(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal | Region::Dendy => 50.007,
+        }
+    }
+}
@@ -1,18 +1,23 @@
+use alloc::{boxed::Box, vec};
+
 pub mod palette;
 
-const FRAME_WIDTH: usize = 256;
-const FRAME_HEIGHT: usize = 240;
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
 
 type FrameData = [u8; FRAME_HEIGHT * FRAME_WIDTH * 3];
 
+// Kept on the heap, a frame is too big for the stack of small devices
 pub struct Frame {
-    data: FrameData,
+    data: Box<FrameData>,
 }
 
 impl Frame {
     pub fn new() -> Self {
+        let data = vec![0; FRAME_HEIGHT * FRAME_WIDTH * 3].into_boxed_slice();
+
         Self {
-            data: [0; FRAME_HEIGHT * FRAME_WIDTH * 3],
+            data: data.try_into().unwrap(),
         }
     }
 
@@ -31,6 +36,12 @@ impl Frame {
     }
 }
 
+#[derive(Clone, Copy)]
+pub struct SpritePixel {
+    pub color: u8,
+    pub behind_background: bool,
+}
+
 pub struct ViewPortRect {
     pub x1: usize,
     pub y1: usize,
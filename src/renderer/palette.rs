@@ -1,5 +1,4 @@
 #[rustfmt::skip]
-
 pub static SYSTEM_PALLETE: [(u8,u8,u8); 64] = [
     (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96), (0xA1, 0x00, 0x5E),
     (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00), (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00),
@@ -15,3 +14,57 @@ pub static SYSTEM_PALLETE: [(u8,u8,u8); 64] = [
     (0xFF, 0xEF, 0xA6), (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
     (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11)
 ];
+
+// Colour emphasis attenuates the channels that are not emphasised, the
+// blacks in columns $xE and $xF are left untouched. Index with
+// `emphasis << 6 | color` where emphasis is the BGR bits of PPUMASK.
+pub static EMPHASIS_PALETTE: [(u8, u8, u8); 512] = emphasis_palette();
+
+const EMPHASIS_ATTENUATION: u32 = 816;
+
+const fn emphasis_palette() -> [(u8, u8, u8); 512] {
+    let mut palette = [(0, 0, 0); 512];
+    let mut emphasis = 0;
+
+    while emphasis < 8 {
+        let mut color = 0;
+
+        while color < 64 {
+            let (red, green, blue) = SYSTEM_PALLETE[color];
+
+            palette[emphasis << 6 | color] = if color & 0x0E == 0x0E || emphasis == 0 {
+                (red, green, blue)
+            } else {
+                (
+                    attenuate(red, emphasis & 0b001 == 0),
+                    attenuate(green, emphasis & 0b010 == 0),
+                    attenuate(blue, emphasis & 0b100 == 0),
+                )
+            };
+
+            color += 1;
+        }
+
+        emphasis += 1;
+    }
+
+    palette
+}
+
+const fn attenuate(channel: u8, dim: bool) -> u8 {
+    if dim {
+        (channel as u32 * EMPHASIS_ATTENUATION / 1000) as u8
+    } else {
+        channel
+    }
+}
+
+pub fn output_color(color: u8, greyscale: bool, emphasis: u8) -> (u8, u8, u8) {
+    let color = if greyscale {
+        color & 0x30
+    } else {
+        color & 0x3F
+    };
+
+    EMPHASIS_PALETTE[((emphasis as usize & 0b111) << 6) | color as usize]
+}
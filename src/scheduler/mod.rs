@@ -0,0 +1,172 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{ppu::PPU, IrqSource, NES};
+
+// Every component runs off the same master clock through its own divider,
+// NTSC has a 21.477272 MHz crystal which gives a 3:1 PPU to CPU ratio
+pub const NTSC_CPU_DIVIDER: u64 = 12;
+pub const NTSC_PPU_DIVIDER: u64 = 4;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Event {
+    SetIrq(IrqSource),
+    ClearIrq(IrqSource),
+    FrameComplete,
+}
+
+pub struct Scheduler {
+    pub cpu_divider: u64,
+    pub ppu_divider: u64,
+    master_clock: u64,
+    ppu_clock: u64,
+    // Sorted by due time, the next event to fire is last
+    events: Vec<(u64, Event)>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            cpu_divider: NTSC_CPU_DIVIDER,
+            ppu_divider: NTSC_PPU_DIVIDER,
+            master_clock: 0,
+            ppu_clock: 0,
+            events: vec![],
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn master_clock(&self) -> u64 {
+        self.master_clock
+    }
+
+    pub fn reset(&mut self) {
+        self.master_clock = 0;
+        self.ppu_clock = 0;
+        self.events.clear();
+    }
+
+    // Events due at the same time fire in the order they were scheduled
+    pub fn schedule(&mut self, at: u64, event: Event) {
+        let index = self.events.partition_point(|(due, _)| *due > at);
+
+        self.events.insert(index, (at, event));
+    }
+
+    fn pop_due(&mut self) -> Option<Event> {
+        match self.events.last() {
+            Some((due, _)) if *due <= self.master_clock => {
+                self.events.pop().map(|(_, event)| event)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl NES {
+    // Schedules an event a number of CPU cycles from now
+    pub fn schedule_event(&mut self, cpu_cycles: u64, event: Event) {
+        let at = self.scheduler.master_clock + cpu_cycles * self.scheduler.cpu_divider;
+
+        self.scheduler.schedule(at, event);
+    }
+
+    // Moves the master clock on by one CPU cycle, catching the PPU up to it and
+    // firing any events that became due. Returns true if a frame was completed.
+    pub(crate) fn advance_master_clock(&mut self) -> bool {
+        let mut new_frame = false;
+
+        self.scheduler.master_clock += self.scheduler.cpu_divider;
+
+        while self.scheduler.ppu_clock + self.scheduler.ppu_divider <= self.scheduler.master_clock {
+            self.scheduler.ppu_clock += self.scheduler.ppu_divider;
+            new_frame |= self.ppu_tick();
+        }
+
+        while let Some(event) = self.scheduler.pop_due() {
+            match event {
+                Event::SetIrq(source) => self.set_irq(source, true),
+                Event::ClearIrq(source) => self.set_irq(source, false),
+                Event::FrameComplete => new_frame = true,
+            }
+        }
+
+        new_frame
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::cpu::Bus;
+
+    #[allow(dead_code)]
+    fn dots_after(nes: &mut NES, cpu_cycles: usize) -> usize {
+        let start = nes.ppu_scanline * 341 + nes.ppu_cycles;
+
+        for _ in 0..cpu_cycles {
+            nes.cpu_tick();
+        }
+
+        nes.ppu_scanline * 341 + nes.ppu_cycles - start
+    }
+
+    #[test]
+    fn ntsc_runs_three_dots_per_cpu_cycle() {
+        let mut nes = NES::default();
+
+        assert_eq!(dots_after(&mut nes, 1), 3);
+        assert_eq!(dots_after(&mut nes, 100), 300);
+    }
+
+    #[test]
+    fn fractional_ratios_are_kept_in_step() {
+        let mut nes = NES::default();
+
+        nes.scheduler.cpu_divider = 16;
+        nes.scheduler.ppu_divider = 5;
+
+        assert_eq!(dots_after(&mut nes, 1), 3);
+        assert_eq!(dots_after(&mut nes, 4), 13);
+        assert_eq!(dots_after(&mut nes, 50), 160);
+    }
+
+    #[test]
+    fn scheduled_irq_fires_on_its_cycle() {
+        let mut nes = NES::default();
+
+        nes.schedule_event(10, Event::ClearIrq(IrqSource::Mapper));
+        nes.schedule_event(5, Event::SetIrq(IrqSource::Mapper));
+
+        for _ in 0..4 {
+            nes.cpu_tick();
+        }
+
+        assert!(!nes.irq_asserted());
+
+        nes.cpu_tick();
+
+        assert!(nes.irq_asserted());
+
+        for _ in 0..5 {
+            nes.cpu_tick();
+        }
+
+        assert!(!nes.irq_asserted());
+    }
+
+    #[test]
+    fn scheduled_frame_complete_is_reported() {
+        let mut nes = NES::default();
+
+        nes.schedule_event(2, Event::FrameComplete);
+        nes.cpu_tick();
+
+        assert!(!nes.frame_complete);
+
+        nes.cpu_tick();
+
+        assert!(nes.frame_complete);
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::string::String;
+
+use crate::{
+    cpu::{Bus, Jam},
+    NES,
+};
+
+// Most of blargg's test ROMs, and the suites written since in the same style,
+// report through PRG RAM. $6001-$6003 hold a signature once the status byte at
+// $6000 is valid, and $6004 onwards is the text printed on screen.
+const STATUS: u16 = 0x6000;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const MESSAGE: u16 = 0x6004;
+const MESSAGE_END: u16 = 0x7FFF;
+
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_NEEDS_RESET: u8 = 0x81;
+
+// The ROMs ask for the reset button to be pressed after at least 100ms
+const RESET_DELAY_FRAMES: usize = 6;
+
+#[derive(Debug, PartialEq)]
+pub enum TestOutcome {
+    Passed,
+    // The result code written to $6000, which test ROMs document per test
+    Failed(u8),
+    // A KIL opcode stopped the CPU, the ROM can't finish from here
+    Jammed(Jam),
+    TimedOut,
+}
+
+#[derive(Debug)]
+pub struct TestReport {
+    pub outcome: TestOutcome,
+    pub message: String,
+    pub frames: usize,
+}
+
+impl TestReport {
+    // 0 on a pass, the result code on a failure, 254 if the CPU jammed and 255
+    // if the ROM never finished
+    pub fn exit_code(&self) -> u8 {
+        match self.outcome {
+            TestOutcome::Passed => 0,
+            TestOutcome::Failed(code) => code,
+            TestOutcome::Jammed(_) => 0xFE,
+            TestOutcome::TimedOut => 0xFF,
+        }
+    }
+}
+
+impl NES {
+    // Runs a test ROM until it writes its result to $6000, pressing reset when
+    // it asks for it, or until the CPU jams or frame_limit frames have passed
+    pub fn run_test_rom(&mut self, frame_limit: usize) -> TestReport {
+        let mut reset_at = None;
+
+        for frame in 1..=frame_limit {
+            self.run_frame();
+
+            if let Some(jam) = self.cpu.jammed() {
+                return TestReport {
+                    outcome: TestOutcome::Jammed(jam),
+                    message: self.test_message(),
+                    frames: frame,
+                };
+            }
+
+            if !self.test_status_valid() {
+                continue;
+            }
+
+            match self.peek(STATUS) {
+                STATUS_RUNNING => reset_at = None,
+                STATUS_NEEDS_RESET => {
+                    let due = *reset_at.get_or_insert(frame + RESET_DELAY_FRAMES);
+
+                    if frame >= due {
+                        reset_at = None;
+                        self.reset();
+                    }
+                }
+                code => {
+                    let outcome = if code == 0 {
+                        TestOutcome::Passed
+                    } else {
+                        TestOutcome::Failed(code)
+                    };
+
+                    return TestReport {
+                        outcome,
+                        message: self.test_message(),
+                        frames: frame,
+                    };
+                }
+            }
+        }
+
+        TestReport {
+            outcome: TestOutcome::TimedOut,
+            message: self.test_message(),
+            frames: frame_limit,
+        }
+    }
+
+    fn test_status_valid(&self) -> bool {
+        (0..3).all(|i| self.peek(STATUS + 1 + i) == SIGNATURE[i as usize])
+    }
+
+    fn test_message(&self) -> String {
+        if !self.test_status_valid() {
+            return String::new();
+        }
+
+        (MESSAGE..=MESSAGE_END)
+            .map(|addr| self.peek(addr))
+            .take_while(|&byte| byte != 0)
+            .map(char::from)
+            .collect()
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::{cartridge, cpu::assembler::assemble};
+    #[allow(unused_imports)]
+    use alloc::{vec, vec::Vec};
+
+    // LDA #; STA abs for each byte
+    #[allow(dead_code)]
+    fn store_bytes(stores: &[(u16, u8)]) -> Vec<u8> {
+        let mut program = vec![];
+
+        for &(addr, data) in stores {
+            let [low, high] = addr.to_le_bytes();
+
+            program.extend([0xA9, data, 0x8D, low, high]);
+        }
+
+        program
+    }
+
+    // Stores the bytes from $8000 and then loops forever
+    #[allow(dead_code)]
+    fn store_program(stores: &[(u16, u8)]) -> Vec<u8> {
+        let mut program = store_bytes(stores);
+
+        let [low, high] = (0x8000 + program.len() as u16).to_le_bytes();
+        program.extend([0x4C, low, high]);
+
+        program
+    }
+
+    #[allow(dead_code)]
+    fn report_program(status: u8, message: &str) -> Vec<u8> {
+        let mut stores = vec![(0x6001, 0xDE), (0x6002, 0xB0), (0x6003, 0x61)];
+
+        for (i, byte) in message.bytes().chain([0]).enumerate() {
+            stores.push((MESSAGE + i as u16, byte));
+        }
+
+        stores.push((STATUS, status));
+
+        store_program(&stores)
+    }
+
+    #[allow(dead_code)]
+    fn test_rom_nes(program: Vec<u8>) -> NES {
+        NES::from_cartridge(cartridge::test::test_rom(Some(program)))
+    }
+
+    #[test]
+    fn passing_rom_reports_its_message() {
+        let mut nes = test_rom_nes(report_program(0x00, "Passed\n"));
+        let report = nes.run_test_rom(10);
+
+        assert_eq!(report.outcome, TestOutcome::Passed);
+        assert_eq!(report.message, "Passed\n");
+        assert_eq!(report.frames, 1);
+        assert_eq!(report.exit_code(), 0);
+    }
+
+    #[test]
+    fn failing_rom_reports_its_result_code() {
+        let mut nes = test_rom_nes(report_program(0x03, "Failed #3"));
+        let report = nes.run_test_rom(10);
+
+        assert_eq!(report.outcome, TestOutcome::Failed(3));
+        assert_eq!(report.message, "Failed #3");
+        assert_eq!(report.exit_code(), 3);
+    }
+
+    #[test]
+    fn rom_still_running_times_out() {
+        let mut nes = test_rom_nes(report_program(STATUS_RUNNING, "Running"));
+        let report = nes.run_test_rom(10);
+
+        assert_eq!(report.outcome, TestOutcome::TimedOut);
+        assert_eq!(report.message, "Running");
+        assert_eq!(report.frames, 10);
+    }
+
+    #[test]
+    fn jammed_rom_stops_early() {
+        let mut nes = test_rom_nes(assemble("NOP\nKIL").unwrap());
+        let report = nes.run_test_rom(10);
+
+        assert_eq!(
+            report.outcome,
+            TestOutcome::Jammed(Jam {
+                program_counter: 0x8001,
+                opcode: 0x02,
+            })
+        );
+        assert_eq!(report.frames, 1);
+        assert_eq!(report.exit_code(), 0xFE);
+    }
+
+    #[test]
+    fn status_is_ignored_without_the_signature() {
+        let mut nes = test_rom_nes(store_program(&[(STATUS, 0x00)]));
+        let report = nes.run_test_rom(3);
+
+        assert_eq!(report.outcome, TestOutcome::TimedOut);
+        assert_eq!(report.message, "");
+    }
+
+    #[test]
+    fn reset_is_pressed_when_requested() {
+        // Asks for a reset the first time through and passes the second, the
+        // counter at $6100 survives the reset in PRG RAM
+        let program = assemble(
+            "
+            LDA #$DE
+            STA $6001
+            LDA #$B0
+            STA $6002
+            LDA #$61
+            STA $6003
+            INC $6100
+            LDA $6100
+            CMP #2
+            BEQ pass
+            LDA #$81
+            STA $6000
+        wait:
+            JMP wait
+        pass:
+            LDA #0
+            STA $6000
+        done:
+            JMP done
+        ",
+        )
+        .unwrap();
+
+        let mut nes = test_rom_nes(program);
+        let report = nes.run_test_rom(20);
+
+        assert_eq!(report.outcome, TestOutcome::Passed);
+        assert_eq!(report.frames, 2 + RESET_DELAY_FRAMES);
+    }
+}
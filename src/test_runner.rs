@@ -0,0 +1,54 @@
+// Headless runner for test ROMs that report through $6000, prints the result
+// text and exits with the result code so it can be scripted
+//
+// usage: test_runner <rom> [frame limit]
+
+use std::process::ExitCode;
+
+use lynes::{cartridge::Cartridge, test_rom::TestOutcome, NES};
+
+// A little over two minutes of NTSC frames, longer than any of blargg's ROMs
+const DEFAULT_FRAME_LIMIT: usize = 8000;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+
+    let Some(rom_file) = args.next() else {
+        eprintln!("usage: test_runner <rom> [frame limit]");
+        return ExitCode::from(2);
+    };
+
+    let frame_limit = match args.next().map(|arg| arg.parse()) {
+        None => DEFAULT_FRAME_LIMIT,
+        Some(Ok(limit)) => limit,
+        Some(Err(_)) => {
+            eprintln!("frame limit must be a number");
+            return ExitCode::from(2);
+        }
+    };
+
+    let cart = match Cartridge::load(&rom_file) {
+        Ok(cart) => cart,
+        Err(err) => {
+            eprintln!("unable to load {}: {}", rom_file, err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut nes = NES::from_cartridge(cart);
+    let report = nes.run_test_rom(frame_limit);
+
+    print!("{}", report.message);
+
+    match report.outcome {
+        TestOutcome::Passed => println!("\npassed after {} frames", report.frames),
+        TestOutcome::Failed(code) => println!("\nfailed with code {}", code),
+        TestOutcome::Jammed(jam) => println!(
+            "\njammed by opcode {:02X} at {:04X}",
+            jam.opcode, jam.program_counter
+        ),
+        TestOutcome::TimedOut => println!("\nno result after {} frames", report.frames),
+    }
+
+    ExitCode::from(report.exit_code())
+}
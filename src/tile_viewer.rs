@@ -4,13 +4,13 @@ use lynes::{
 };
 use sdl2::{event::Event, keyboard::Keycode, pixels::PixelFormatEnum};
 
-fn show_tile_bank(chr_rom: &Vec<u8>, bank: usize) -> Frame {
+fn show_tile_bank(chr_rom: &[u8], bank: usize) -> Frame {
     assert!(bank <= 1);
 
     let mut frame = Frame::new();
     let mut tile_y = 0;
     let mut tile_x = 0;
-    let bank = (bank * 0x1000) as usize;
+    let bank = bank * 0x1000;
 
     for tile_n in 0..255 {
         if tile_n != 0 && tile_n % 20 == 0 {
@@ -25,8 +25,8 @@ fn show_tile_bank(chr_rom: &Vec<u8>, bank: usize) -> Frame {
 
             for x in (0..=7).rev() {
                 let value = (1 & upper) << 1 | (1 & lower);
-                upper = upper >> 1;
-                lower = lower >> 1;
+                upper >>= 1;
+                lower >>= 1;
                 let rgb = match value {
                     0 => palette::SYSTEM_PALLETE[0x01],
                     1 => palette::SYSTEM_PALLETE[0x23],
@@ -63,7 +63,7 @@ fn main() {
         .unwrap();
 
     //load the game
-    let rom = Cartridge::load("roms/pacman.nes");
+    let rom = Cartridge::load("roms/pacman.nes").unwrap();
     let right_bank = show_tile_bank(&rom.chr_rom, 0);
 
     texture.update(None, right_bank.data(), 256 * 3).unwrap();
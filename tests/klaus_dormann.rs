@@ -0,0 +1,113 @@
+// Klaus Dormann's 6502 functional and decimal tests, run on a bare CPU with 64KB
+// of RAM. The binaries aren't shipped with lynes, build or download them into
+// tests/roms (6502_functional_test.bin as the stock 64KB image and
+// 6502_decimal_test.bin assembled at $0200) and run them with
+// `cargo test -- --ignored`.
+
+use std::path::Path;
+
+use lynes::cpu::{flat_ram::FlatRam, Cpu};
+
+const FUNCTIONAL_TEST: &str = "6502_functional_test.bin";
+const FUNCTIONAL_START: u16 = 0x0400;
+const FUNCTIONAL_SUCCESS: u16 = 0x3469;
+const FUNCTIONAL_TEST_CASE: u16 = 0x0200;
+
+const DECIMAL_TEST: &str = "6502_decimal_test.bin";
+const DECIMAL_START: u16 = 0x0200;
+const DECIMAL_ERROR: u16 = 0x000B;
+
+// Where the decimal test ends up once it returns or hits BRK
+const RETURN_TRAP: u16 = 0xFFF0;
+
+const CYCLE_LIMIT: usize = 200_000_000;
+
+fn load_rom(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/roms")
+        .join(name);
+
+    std::fs::read(&path).unwrap_or_else(|err| panic!("unable to read {}: {}", path.display(), err))
+}
+
+fn decimal_cpu(start: u16) -> Cpu {
+    let mut cpu = Cpu::default();
+
+    cpu.decimal_mode = true;
+    cpu.registers.program_counter = start;
+
+    cpu
+}
+
+// Runs until the program jumps or branches to itself, which is how both tests
+// signal success and failure, and returns the address it got stuck on
+fn run_until_trap(cpu: &mut Cpu, bus: &mut FlatRam) -> u16 {
+    loop {
+        let program_counter = cpu.registers.program_counter;
+
+        cpu.clock(bus);
+
+        if cpu.registers.program_counter == program_counter {
+            return program_counter;
+        }
+
+        assert!(
+            bus.cycles < CYCLE_LIMIT,
+            "no trap after {} cycles, PC at {:04X}",
+            bus.cycles,
+            cpu.registers.program_counter
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs tests/roms/6502_functional_test.bin"]
+fn functional_test() {
+    let rom = load_rom(FUNCTIONAL_TEST);
+
+    let mut bus = FlatRam::default();
+    bus.load(0x0000, &rom);
+
+    let mut cpu = decimal_cpu(FUNCTIONAL_START);
+    let trap = run_until_trap(&mut cpu, &mut bus);
+
+    assert_eq!(
+        trap,
+        FUNCTIONAL_SUCCESS,
+        "trapped at {:04X} in test case {:02X}, A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+        trap,
+        bus.memory[FUNCTIONAL_TEST_CASE as usize],
+        cpu.registers.accumulator,
+        cpu.registers.x,
+        cpu.registers.y,
+        cpu.registers.status.bits(),
+        cpu.registers.stack_pointer,
+    );
+}
+
+#[test]
+#[ignore = "needs tests/roms/6502_decimal_test.bin"]
+fn decimal_test() {
+    let rom = load_rom(DECIMAL_TEST);
+
+    let mut bus = FlatRam::default();
+    bus.load(DECIMAL_START, &rom);
+
+    // JMP to itself, reached through RTS from the top of the stack or the BRK
+    // vector
+    let [low, high] = RETURN_TRAP.to_le_bytes();
+    let [return_low, return_high] = RETURN_TRAP.wrapping_sub(1).to_le_bytes();
+
+    bus.load(RETURN_TRAP, &[0x4C, low, high]);
+    bus.load(0xFFFE, &[low, high]);
+    bus.load(0x01FE, &[return_low, return_high]);
+
+    let mut cpu = decimal_cpu(DECIMAL_START);
+    let trap = run_until_trap(&mut cpu, &mut bus);
+
+    assert_eq!(
+        bus.memory[DECIMAL_ERROR as usize], 0,
+        "decimal test failed, trapped at {:04X} with operands {:02X} and {:02X}",
+        trap, bus.memory[0x0000], bus.memory[0x0001],
+    );
+}
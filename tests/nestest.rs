@@ -0,0 +1,48 @@
+// Runs kevtris' nestest from its automation entry point and compares the trace
+// against the reference log, line by line. Neither file is shipped with lynes,
+// put nestest.nes and nestest.log in tests/roms and run with
+// `cargo test -- --ignored`.
+
+use std::path::Path;
+
+use lynes::{cartridge::Cartridge, logger, NES};
+
+// Where the automated run starts, instead of the reset vector which waits for
+// input on screen
+const AUTOMATION_START: u16 = 0xC000;
+
+// Lines of the log shown before the one that differs
+const CONTEXT_LINES: usize = 3;
+
+#[test]
+#[ignore = "needs tests/roms/nestest.nes and nestest.log"]
+fn nestest_matches_reference_log() {
+    let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+    let rom_path = roms.join("nestest.nes");
+    let log_path = roms.join("nestest.log");
+
+    let expected = std::fs::read_to_string(&log_path)
+        .unwrap_or_else(|err| panic!("unable to read {}: {}", log_path.display(), err));
+    let expected: Vec<&str> = expected.lines().map(str::trim_end).collect();
+
+    let mut nes = NES::from_cartridge(Cartridge::load(rom_path.to_str().unwrap()).unwrap());
+    nes.cpu.registers.program_counter = AUTOMATION_START;
+
+    for (index, wanted) in expected.iter().enumerate() {
+        let actual = logger::trace(&nes);
+
+        if actual != *wanted {
+            let context = expected[index.saturating_sub(CONTEXT_LINES)..index].join("\n");
+
+            panic!(
+                "trace differs from nestest.log at line {}\n\n{}\n\nexpected: {}\n  actual: {}",
+                index + 1,
+                context,
+                wanted,
+                actual
+            );
+        }
+
+        nes.step_instruction();
+    }
+}
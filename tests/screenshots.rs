@@ -0,0 +1,223 @@
+// Rendering regression tests. Each run in tests/screenshots.txt plays a ROM for
+// a number of frames with scripted input and compares a hash of the last frame
+// against the recorded one. Frames that don't match are written out as PPM
+// images next to the test binaries so they can be looked at.
+//
+// ROMs ending in .s are assembly sources in tests/screenshots, built into an
+// NROM cart with CHR RAM, so some runs don't need anything outside the repo.
+//
+// UPDATE_SCREENSHOTS=1 records new hashes instead of checking them.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use lynes::{
+    cartridge::{test::RomBuilder, Cartridge},
+    cpu::assembler::assemble,
+    input::ButtonState,
+    renderer::Frame,
+    NES,
+};
+
+const MANIFEST: &str = "tests/screenshots.txt";
+const SOURCES: &str = "tests/screenshots";
+const UPDATE_VAR: &str = "UPDATE_SCREENSHOTS";
+
+struct Run {
+    rom: String,
+    frames: usize,
+    // Frame to start holding the buttons from, sorted by frame
+    input: Vec<(usize, u8)>,
+    hash: Option<u64>,
+}
+
+fn parse_buttons(buttons: &str) -> u8 {
+    if buttons == "-" {
+        return 0;
+    }
+
+    buttons.split('+').fold(0, |state, button| {
+        let bit = match button {
+            "a" => 0,
+            "b" => 1,
+            "select" => 2,
+            "start" => 3,
+            "up" => 4,
+            "down" => 5,
+            "left" => 6,
+            "right" => 7,
+            _ => panic!("unknown button {}", button),
+        };
+
+        state | 1 << bit
+    })
+}
+
+fn parse_input(input: &str) -> Vec<(usize, u8)> {
+    if input == "-" {
+        return vec![];
+    }
+
+    let mut input: Vec<_> = input
+        .split(',')
+        .map(|step| {
+            let (frame, buttons) = step
+                .split_once(':')
+                .unwrap_or_else(|| panic!("input should be frame:buttons, got {}", step));
+
+            (frame.parse().unwrap(), parse_buttons(buttons))
+        })
+        .collect();
+    input.sort_by_key(|(frame, _)| *frame);
+
+    input
+}
+
+fn parse_run(line: &str) -> Run {
+    let columns: Vec<_> = line.split_whitespace().collect();
+
+    let [rom, frames, input, hash] = columns[..] else {
+        panic!("expected rom, frames, input and hash in \"{}\"", line);
+    };
+
+    Run {
+        rom: rom.to_string(),
+        frames: frames.parse().unwrap(),
+        input: parse_input(input),
+        hash: match hash {
+            "-" => None,
+            hash => Some(u64::from_str_radix(hash, 16).unwrap()),
+        },
+    }
+}
+
+fn is_run(line: &str) -> bool {
+    let line = line.trim();
+
+    !line.is_empty() && !line.starts_with('#')
+}
+
+// FNV-1a, stable across platforms and Rust versions unlike the std hasher
+fn hash_frame(frame: &Frame) -> u64 {
+    frame
+        .data()
+        .iter()
+        .fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+        })
+}
+
+// None when the ROM isn't in tests/roms
+fn load_cartridge(root: &Path, rom: &str) -> Option<Cartridge> {
+    if rom.ends_with(".s") {
+        let path = root.join(SOURCES).join(rom);
+        let source = std::fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("unable to read {}: {}", path.display(), err));
+        let program =
+            assemble(&source).unwrap_or_else(|err| panic!("{}:{}: {}", rom, err.line, err.message));
+
+        return Some(
+            RomBuilder::new()
+                .prg_rom(program)
+                .chr_ram(0x2000)
+                .cartridge(),
+        );
+    }
+
+    let path = root.join("tests/roms").join(rom);
+
+    if !path.exists() {
+        eprintln!("skipping, {} not found", path.display());
+        return None;
+    }
+
+    Some(Cartridge::load(path.to_str().unwrap()).unwrap())
+}
+
+fn play(cart: Cartridge, run: &Run) -> u64 {
+    let mut nes = NES::from_cartridge(cart);
+    let mut input = run.input.iter().peekable();
+
+    for frame in 0..run.frames {
+        while let Some((_, buttons)) = input.next_if(|(start, _)| *start <= frame) {
+            nes.controller().button_state = ButtonState::from_bytes([*buttons]);
+        }
+
+        nes.run_frame();
+    }
+
+    let hash = hash_frame(nes.frame());
+
+    if run.hash != Some(hash) {
+        save_ppm(&run.rom, nes.frame());
+    }
+
+    hash
+}
+
+fn save_ppm(rom: &str, frame: &Frame) {
+    let name = rom.replace(['/', '\\'], "_") + ".ppm";
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+
+    let mut image = b"P6\n256 240\n255\n".to_vec();
+    image.extend_from_slice(frame.data());
+
+    std::fs::write(&path, image).unwrap();
+    eprintln!("{}: wrote the frame to {}", rom, path.display());
+}
+
+#[test]
+fn screenshots_match() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let manifest_path = root.join(MANIFEST);
+    let manifest = std::fs::read_to_string(&manifest_path).unwrap();
+    let update = std::env::var_os(UPDATE_VAR).is_some();
+
+    let mut updated = String::new();
+    let mut failed = vec![];
+
+    for line in manifest.lines() {
+        if !is_run(line) {
+            writeln!(updated, "{}", line).unwrap();
+            continue;
+        }
+
+        let run = parse_run(line);
+
+        let Some(cart) = load_cartridge(root, &run.rom) else {
+            writeln!(updated, "{}", line).unwrap();
+            continue;
+        };
+
+        let hash = play(cart, &run);
+
+        match run.hash {
+            _ if update => {
+                let (run_columns, _) = line.trim_end().rsplit_once(char::is_whitespace).unwrap();
+
+                writeln!(updated, "{} {:016x}", run_columns.trim_end(), hash).unwrap();
+            }
+            Some(golden) if golden == hash => writeln!(updated, "{}", line).unwrap(),
+            golden => {
+                let golden =
+                    golden.map_or("nothing".to_string(), |golden| format!("{:016x}", golden));
+
+                failed.push(format!("{}: {:016x}, recorded {}", run.rom, hash, golden));
+                writeln!(updated, "{}", line).unwrap();
+            }
+        }
+    }
+
+    if update {
+        std::fs::write(&manifest_path, updated).unwrap();
+    }
+
+    assert!(
+        failed.is_empty(),
+        "frames changed, run with {}=1 if this is expected\n{}",
+        UPDATE_VAR,
+        failed.join("\n")
+    );
+}
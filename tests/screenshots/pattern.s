@@ -0,0 +1,146 @@
+; Background and sprite test pattern for tests/screenshots.rs. Uploads its own
+; tiles to CHR RAM, so it needs nothing outside the repo.
+
+PPUCTRL = $2000
+PPUMASK = $2001
+PPUSTATUS = $2002
+PPUSCROLL = $2005
+PPUADDR = $2006
+PPUDATA = $2007
+OAMDMA = $4014
+
+row = $00
+OAM = $0200
+
+reset:
+        SEI
+        LDX #$FF
+        TXS
+        LDA #0
+        STA PPUCTRL
+        STA PPUMASK
+
+        ; The PPU ignores writes until it's warmed up
+        BIT PPUSTATUS
+vblank1:
+        BIT PPUSTATUS
+        BPL vblank1
+vblank2:
+        BIT PPUSTATUS
+        BPL vblank2
+
+        ; Tiles 1 to 3 at $0010
+        LDA #$00
+        STA PPUADDR
+        LDA #$10
+        STA PPUADDR
+        LDX #0
+tiles:
+        LDA tile_data,X
+        STA PPUDATA
+        INX
+        CPX #48
+        BNE tiles
+
+        LDA #$3F
+        STA PPUADDR
+        LDA #$00
+        STA PPUADDR
+        LDX #0
+palettes:
+        LDA palette_data,X
+        STA PPUDATA
+        INX
+        CPX #32
+        BNE palettes
+
+        ; Diagonal stripes of tiles 0 to 3, (column + row) & 3
+        LDA #$20
+        STA PPUADDR
+        LDA #$00
+        STA PPUADDR
+        LDA #0
+        STA row
+rows:
+        LDX #0
+columns:
+        TXA
+        CLC
+        ADC row
+        AND #3
+        STA PPUDATA
+        INX
+        CPX #32
+        BNE columns
+        INC row
+        LDA row
+        CMP #30
+        BNE rows
+
+        ; Each 32x32 area uses palettes 0 to 3 in its four corners
+        LDA #$E4
+        LDX #64
+attributes:
+        STA PPUDATA
+        DEX
+        BNE attributes
+
+        ; Hide every sprite, then place four copies of tile 3 with different
+        ; flips, palettes and priority
+        LDA #$FF
+        LDX #0
+hide:
+        STA OAM,X
+        INX
+        BNE hide
+
+        LDX #0
+sprites:
+        LDA sprite_data,X
+        STA OAM,X
+        INX
+        CPX #16
+        BNE sprites
+
+        LDA #>OAM
+        STA OAMDMA
+
+        LDA #0
+        STA PPUSCROLL
+        STA PPUSCROLL
+        STA PPUCTRL
+        LDA #%00011110
+        STA PPUMASK
+
+done:
+        JMP done
+
+tile_data:
+        ; 1, solid colour 1
+        .byte $FF, $FF, $FF, $FF, $FF, $FF, $FF, $FF
+        .byte $00, $00, $00, $00, $00, $00, $00, $00
+        ; 2, checkerboard of colours 2 and 3
+        .byte $AA, $55, $AA, $55, $AA, $55, $AA, $55
+        .byte $FF, $FF, $FF, $FF, $FF, $FF, $FF, $FF
+        ; 3, an arrow pointing up and left in colours 1 to 3
+        .byte %11111000, %11100000, %11110000, %10111000
+        .byte %10011100, %00001110, %00000111, %00000010
+        .byte %11110000, %11000000, %10000000, %10000000
+        .byte %00000000, %00000000, %00000110, %00000011
+
+palette_data:
+        .byte $0F, $16, $2A, $12
+        .byte $0F, $27, $17, $07
+        .byte $0F, $21, $31, $01
+        .byte $0F, $19, $29, $09
+        .byte $0F, $30, $10, $00
+        .byte $0F, $14, $24, $04
+        .byte $0F, $38, $28, $18
+        .byte $0F, $11, $21, $31
+
+sprite_data:
+        ; y, tile, attributes, x
+        .byte 100, 3, %00000000, 100
+        .byte 100, 3, %01000001, 116
+        .byte 116, 3, %10000010, 100
+        .byte 116, 3, %11100011, 116
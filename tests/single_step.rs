@@ -0,0 +1,230 @@
+// Per-opcode conformance tests from the SingleStepTests 6502 corpus. Each file
+// holds thousands of single instructions with the state before and after and
+// every bus access in between. The corpus isn't shipped with lynes, put the
+// nes6502 JSON files (00.json to ff.json) in tests/roms/single_step and run
+// with `cargo test -- --ignored`.
+
+use std::path::Path;
+
+use lynes::cpu::{Bus, Cpu, UnstableOpcodes};
+use serde_json::Value;
+
+// Failures printed for each opcode before the rest are only counted
+const REPORTED_FAILURES: usize = 3;
+
+#[derive(Debug, PartialEq)]
+struct Cycle {
+    addr: u16,
+    data: u8,
+    write: bool,
+}
+
+// 64KB of RAM that records every access the CPU makes
+struct RecordingBus {
+    memory: Vec<u8>,
+    cycles: Vec<Cycle>,
+}
+
+impl Bus for RecordingBus {
+    fn tick(&mut self) {}
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.memory[addr as usize];
+
+        self.cycles.push(Cycle {
+            addr,
+            data,
+            write: false,
+        });
+
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
+
+        self.cycles.push(Cycle {
+            addr,
+            data,
+            write: true,
+        });
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+}
+
+fn field(state: &Value, name: &str) -> u64 {
+    state[name]
+        .as_u64()
+        .unwrap_or_else(|| panic!("missing {} in {}", name, state))
+}
+
+fn ram(state: &Value) -> impl Iterator<Item = (u16, u8)> + '_ {
+    state["ram"]
+        .as_array()
+        .expect("missing ram")
+        .iter()
+        .map(|entry| {
+            (
+                entry[0].as_u64().unwrap() as u16,
+                entry[1].as_u64().unwrap() as u8,
+            )
+        })
+}
+
+fn setup(initial: &Value) -> (Cpu, RecordingBus) {
+    let mut cpu = Cpu::default();
+    cpu.unstable_opcodes = UnstableOpcodes::SINGLE_STEP_TESTS;
+    let mut bus = RecordingBus {
+        memory: vec![0; 0x10000],
+        cycles: vec![],
+    };
+
+    cpu.registers.program_counter = field(initial, "pc") as u16;
+    cpu.registers.stack_pointer = field(initial, "s") as u8;
+    cpu.registers.accumulator = field(initial, "a") as u8;
+    cpu.registers.x = field(initial, "x") as u8;
+    cpu.registers.y = field(initial, "y") as u8;
+    cpu.registers.status.set_bits(field(initial, "p") as u8);
+
+    for (addr, data) in ram(initial) {
+        bus.memory[addr as usize] = data;
+    }
+
+    (cpu, bus)
+}
+
+// Runs one test case and describes everything that didn't match
+fn run_case(case: &Value) -> Vec<String> {
+    let (mut cpu, mut bus) = setup(&case["initial"]);
+    let expected = &case["final"];
+    let mut errors = vec![];
+
+    let expected_cycles: Vec<Cycle> = case["cycles"]
+        .as_array()
+        .expect("missing cycles")
+        .iter()
+        .map(|cycle| Cycle {
+            addr: cycle[0].as_u64().unwrap() as u16,
+            data: cycle[1].as_u64().unwrap() as u8,
+            write: cycle[2] == "write",
+        })
+        .collect();
+
+    cpu.clock(&mut bus);
+
+    // KIL never finishes, the tests record the bus for a while after it jams
+    while cpu.jammed().is_some() && bus.cycles.len() < expected_cycles.len() {
+        cpu.clock(&mut bus);
+    }
+
+    let registers = [
+        ("pc", cpu.registers.program_counter as u64),
+        ("s", cpu.registers.stack_pointer as u64),
+        ("a", cpu.registers.accumulator as u64),
+        ("x", cpu.registers.x as u64),
+        ("y", cpu.registers.y as u64),
+        ("p", cpu.registers.status.bits() as u64),
+    ];
+
+    for (name, actual) in registers {
+        let wanted = field(expected, name);
+
+        if actual != wanted {
+            errors.push(format!("{}: {:X}, expected {:X}", name, actual, wanted));
+        }
+    }
+
+    for (addr, wanted) in ram(expected) {
+        let actual = bus.memory[addr as usize];
+
+        if actual != wanted {
+            errors.push(format!(
+                "${:04X}: {:02X}, expected {:02X}",
+                addr, actual, wanted
+            ));
+        }
+    }
+
+    if bus.cycles.len() != expected_cycles.len() {
+        errors.push(format!(
+            "took {} cycles, expected {}",
+            bus.cycles.len(),
+            expected_cycles.len()
+        ));
+    }
+
+    let mismatch = bus
+        .cycles
+        .iter()
+        .zip(&expected_cycles)
+        .position(|(actual, wanted)| actual != wanted);
+
+    if let Some(index) = mismatch {
+        errors.push(format!(
+            "cycle {}: {:?}, expected {:?}",
+            index, bus.cycles[index], expected_cycles[index]
+        ));
+    }
+
+    errors
+}
+
+// Returns the number of failing cases in the file
+fn run_opcode(path: &Path, opcode: u8) -> usize {
+    let json = std::fs::read_to_string(path).unwrap();
+    let cases: Value = serde_json::from_str(&json).unwrap();
+    let mut failures = 0;
+
+    for case in cases.as_array().expect("expected a list of tests") {
+        let errors = run_case(case);
+
+        if errors.is_empty() {
+            continue;
+        }
+
+        if failures < REPORTED_FAILURES {
+            let name = case["name"].as_str().unwrap_or_default();
+
+            eprintln!("{:02x} \"{}\": {}", opcode, name, errors.join(", "));
+        }
+
+        failures += 1;
+    }
+
+    failures
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests corpus in tests/roms/single_step"]
+fn all_opcodes() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms/single_step");
+    let mut missing = vec![];
+    let mut failed = vec![];
+
+    for opcode in 0..=0xFF {
+        let path = dir.join(format!("{:02x}.json", opcode));
+
+        if !path.exists() {
+            missing.push(format!("{:02x}", opcode));
+            continue;
+        }
+
+        let failures = run_opcode(&path, opcode);
+
+        if failures > 0 {
+            failed.push(format!("{:02x} ({} cases)", opcode, failures));
+        }
+    }
+
+    assert!(
+        missing.is_empty(),
+        "{} of 256 opcodes missing from {}: {}",
+        missing.len(),
+        dir.display(),
+        missing.join(", ")
+    );
+    assert!(failed.is_empty(), "failing opcodes: {}", failed.join(", "));
+}
@@ -0,0 +1,53 @@
+// Runs every ROM in tests/roms/blargg that reports through the $6000 protocol
+// (cpu_instrs, instr_timing, ppu_vbl_nmi, ...). None are shipped with lynes,
+// copy the single test ROMs of a suite into that folder and run with
+// `cargo test -- --ignored`.
+
+use std::path::Path;
+
+use lynes::{cartridge::Cartridge, test_rom::TestOutcome, NES};
+
+const FRAME_LIMIT: usize = 8000;
+
+#[test]
+#[ignore = "needs test ROMs in tests/roms/blargg"]
+fn blargg_test_roms() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms/blargg");
+
+    let entries = std::fs::read_dir(&dir)
+        .unwrap_or_else(|err| panic!("unable to read {}: {}", dir.display(), err));
+
+    let mut roms: Vec<_> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "nes"))
+        .collect();
+    roms.sort();
+
+    assert!(!roms.is_empty(), "no .nes files in {}", dir.display());
+
+    let mut failed = vec![];
+
+    for rom in &roms {
+        let name = rom.file_name().unwrap().to_string_lossy();
+        let mut nes = NES::from_cartridge(Cartridge::load(rom.to_str().unwrap()).unwrap());
+        let report = nes.run_test_rom(FRAME_LIMIT);
+
+        if report.outcome != TestOutcome::Passed {
+            eprintln!(
+                "{}: {:?}\n{}",
+                name,
+                report.outcome,
+                report.message.trim_end()
+            );
+            failed.push(name.into_owned());
+        }
+    }
+
+    assert!(
+        failed.is_empty(),
+        "{} of {} test ROMs failed: {}",
+        failed.len(),
+        roms.len(),
+        failed.join(", ")
+    );
+}